@@ -0,0 +1,143 @@
+//! In-document anchors that links can scroll to, and heading slugs.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use gtk::{glib, prelude::*};
+
+/// Widgets that can be scrolled to, by anchor name (without the leading `#`).
+///
+/// Widgets are weakly referenced so that labels linking to them do not keep them alive.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Anchors(Rc<RefCell<HashMap<String, glib::WeakRef<gtk::Widget>>>>);
+
+impl Anchors {
+    /// Register an anchor, keeping the first widget registered with a given name that is still
+    /// alive.
+    pub(crate) fn insert(&self, name: String, widget: &impl IsA<gtk::Widget>) {
+        let mut anchors = self.0.borrow_mut();
+        let anchor = anchors
+            .entry(name)
+            .or_insert_with(|| widget.upcast_ref::<gtk::Widget>().downgrade());
+        if anchor.upgrade().is_none() {
+            *anchor = widget.upcast_ref::<gtk::Widget>().downgrade();
+        }
+    }
+
+    /// Remove every anchor, before rendering the document again.
+    pub(crate) fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+
+    /// Widget registered with the given anchor name, if it is still alive.
+    pub(crate) fn get(&self, name: &str) -> Option<gtk::Widget> {
+        self.0
+            .borrow()
+            .get(name)
+            .and_then(|widget| widget.upgrade())
+    }
+
+    /// Scroll to the widget registered with the given anchor name.
+    ///
+    /// Returns `false` if the anchor is unknown or if the widget is not inside a `gtk::ScrolledWindow`.
+    pub(crate) fn scroll_to(&self, name: &str) -> bool {
+        self.get(name)
+            .is_some_and(|widget| scroll_to_widget(&widget))
+    }
+}
+
+/// Generator of GitHub-compatible heading slugs, suffixed with `-1`, `-2`, etc. when a slug is
+/// already taken.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Slugger {
+    /// Number of times each slug was generated from a heading
+    occurrences: HashMap<String, usize>,
+}
+
+impl Slugger {
+    /// Slug of a heading, from its plain text.
+    pub(crate) fn slug(&mut self, text: &str) -> String {
+        // Lowercase, drop punctuation and symbols, and turn each space into a hyphen
+        let original: String = text
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                ' ' => Some('-'),
+                '-' | '_' => Some(c),
+                c if c.is_alphanumeric() => Some(c),
+                _ => None,
+            })
+            .collect();
+
+        let mut slug = original.clone();
+        while self.occurrences.contains_key(&slug) {
+            let count = self.occurrences.entry(original.clone()).or_default();
+            *count += 1;
+            slug = format!("{original}-{count}");
+        }
+        self.occurrences.insert(slug.clone(), 0);
+        slug
+    }
+}
+
+/// Scroll the closest `gtk::ScrolledWindow` ancestor so that the widget is at the top of it.
+pub(crate) fn scroll_to_widget(widget: &gtk::Widget) -> bool {
+    let Some(scrolled_window) = widget
+        .ancestor(gtk::ScrolledWindow::static_type())
+        .and_then(|ancestor| ancestor.downcast::<gtk::ScrolledWindow>().ok())
+    else {
+        return false;
+    };
+    let Some((_, y)) = widget.translate_coordinates(&scrolled_window, 0.0, 0.0) else {
+        return false;
+    };
+    let vadjustment = scrolled_window.vadjustment();
+    vadjustment.set_value(vadjustment.value() + y);
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_slugs_are_suffixed() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("a"), "a");
+        assert_eq!(slugger.slug("a"), "a-1");
+        assert_eq!(slugger.slug("a"), "a-2");
+    }
+
+    #[test]
+    fn suffixes_skip_literal_headings() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("a"), "a");
+        assert_eq!(slugger.slug("a-1"), "a-1");
+        assert_eq!(slugger.slug("a"), "a-2");
+        assert_eq!(slugger.slug("a-1"), "a-1-1");
+
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("a"), "a");
+        assert_eq!(slugger.slug("a"), "a-1");
+        assert_eq!(slugger.slug("a-1"), "a-1-1");
+    }
+
+    #[test]
+    fn punctuation_and_emoji_are_stripped() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("What's new?"), "whats-new");
+        assert_eq!(slugger.slug("  C++ & Rust!  "), "c--rust");
+        assert_eq!(slugger.slug("snake_case-and-kebab"), "snake_case-and-kebab");
+        assert_eq!(slugger.slug("Hello 👋 World"), "hello--world");
+    }
+
+    #[test]
+    fn non_ascii_letters_are_kept() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("Überblick"), "überblick");
+        assert_eq!(slugger.slug("Ça marche"), "ça-marche");
+        assert_eq!(slugger.slug("日本語の見出し"), "日本語の見出し");
+        assert_eq!(slugger.slug("Ελληνικά 2"), "ελληνικά-2");
+    }
+}
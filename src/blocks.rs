@@ -0,0 +1,235 @@
+//! Top-level blocks of a rendered document, so that only the blocks that changed are rendered
+//! again.
+
+use std::collections::{HashMap, VecDeque};
+
+use gtk::prelude::*;
+use markdown::mdast::{Definition, Node};
+
+use crate::{
+    append_widgets_from_children, footnotes, front_matter, html, DocumentCtx, FrontMatter,
+    ListContext, OutlineItem,
+};
+
+/// Widgets rendered from consecutive top-level nodes.
+#[derive(Debug, Clone)]
+struct Block {
+    /// Source of the nodes, or `None` if their position is unknown and they cannot be reused
+    source: Option<String>,
+    widgets: Vec<gtk::Widget>,
+    headings: Vec<OutlineItem>,
+}
+
+/// Blocks of a rendered document, and the source every block depends on.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Blocks {
+    blocks: Vec<Block>,
+    shared_source: Option<String>,
+    has_footnotes: bool,
+}
+
+impl Blocks {
+    /// Render the top-level nodes into the content box, reusing the widgets of the blocks whose
+    /// source did not change.
+    ///
+    /// Every block is rendered again if the front matter or the link definitions changed, or if
+    /// the document has footnotes, as their numbering depends on the whole document.
+    pub(crate) fn update(
+        &mut self,
+        input: &str,
+        children: &[Node],
+        content_box: &gtk::Box,
+        front_matter: Option<&FrontMatter>,
+        doc_ctx: &DocumentCtx,
+    ) {
+        let shared_source = shared_source(children, &doc_ctx.definitions);
+        let has_footnotes = !doc_ctx.footnotes.borrow().is_empty();
+        let full_render = has_footnotes
+            || self.has_footnotes
+            || self.shared_source.as_ref() != Some(&shared_source);
+
+        let mut previous_blocks: HashMap<String, VecDeque<Block>> = HashMap::new();
+        if full_render {
+            while let Some(child) = content_box.first_child() {
+                content_box.remove(&child);
+            }
+            self.blocks.clear();
+        }
+        for block in self.blocks.drain(..) {
+            match &block.source {
+                Some(source) => previous_blocks
+                    .entry(source.clone())
+                    .or_default()
+                    .push_back(block),
+                None => remove_widgets(content_box, &block),
+            }
+        }
+
+        let mut previous_widget: Option<gtk::Widget> = None;
+        let mut index = 0;
+        while index < children.len() {
+            let end = html::block_end(children, index);
+            let nodes = &children[index..end];
+            index = end;
+
+            let source = block_source(input, nodes);
+            let headings_start = doc_ctx.outline.borrow().items().len();
+            let reused = source
+                .as_ref()
+                .and_then(|source| previous_blocks.get_mut(source))
+                .and_then(VecDeque::pop_front);
+            let widgets = match reused {
+                Some(block) => {
+                    for widget in &block.widgets {
+                        content_box.reorder_child_after(widget, previous_widget.as_ref());
+                        previous_widget = Some(widget.clone());
+                    }
+                    // Slugs of reused headings can change with the headings before them
+                    for heading in &block.headings {
+                        let Some(widget) = heading.widget() else {
+                            continue;
+                        };
+                        let text = heading.text();
+                        let slug = doc_ctx.slugger.borrow_mut().slug(&text);
+                        doc_ctx
+                            .outline
+                            .borrow_mut()
+                            .push(heading.level(), &text, &slug, &widget);
+                        doc_ctx.anchors.insert(slug, &widget);
+                    }
+                    block.widgets
+                }
+                None => {
+                    let block_box = render_nodes(nodes, front_matter, doc_ctx);
+                    let mut widgets = Vec::new();
+                    while let Some(widget) = block_box.first_child() {
+                        block_box.remove(&widget);
+                        content_box.insert_child_after(&widget, previous_widget.as_ref());
+                        previous_widget = Some(widget.clone());
+                        widgets.push(widget);
+                    }
+                    widgets
+                }
+            };
+            let headings = doc_ctx.outline.borrow().items()[headings_start..].to_vec();
+            self.blocks.push(Block {
+                source,
+                widgets,
+                headings,
+            });
+        }
+
+        for block in previous_blocks.into_values().flatten() {
+            remove_widgets(content_box, &block);
+        }
+        if full_render {
+            footnotes::append_footnotes_section(content_box, doc_ctx);
+        }
+        self.shared_source = Some(shared_source);
+        self.has_footnotes = has_footnotes;
+    }
+
+    /// Render the top-level nodes of a part of the document after the blocks rendered so far.
+    ///
+    /// The link definitions and footnotes of the part only apply to it, until the whole document
+    /// is rendered with [`Blocks::update`], which reuses the blocks of the parts.
+    pub(crate) fn append(
+        &mut self,
+        input: &str,
+        children: &[Node],
+        content_box: &gtk::Box,
+        front_matter: Option<&FrontMatter>,
+        doc_ctx: &DocumentCtx,
+    ) {
+        let mut index = 0;
+        while index < children.len() {
+            let end = html::block_end(children, index);
+            let nodes = &children[index..end];
+            index = end;
+
+            let headings_start = doc_ctx.outline.borrow().items().len();
+            let block_box = render_nodes(nodes, front_matter, doc_ctx);
+            let mut widgets = Vec::new();
+            while let Some(widget) = block_box.first_child() {
+                block_box.remove(&widget);
+                content_box.append(&widget);
+                widgets.push(widget);
+            }
+            let headings = doc_ctx.outline.borrow().items()[headings_start..].to_vec();
+            self.blocks.push(Block {
+                source: block_source(input, nodes),
+                widgets,
+                headings,
+            });
+        }
+    }
+
+    /// Number of blocks rendered.
+    pub(crate) fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Remove the blocks after the first `len` ones.
+    pub(crate) fn truncate(&mut self, len: usize, content_box: &gtk::Box) {
+        for block in self.blocks.drain(len.min(self.blocks.len())..) {
+            remove_widgets(content_box, &block);
+        }
+    }
+}
+
+/// Render consecutive top-level nodes into a new box, whose children are the widgets of their
+/// block.
+fn render_nodes(
+    nodes: &[Node],
+    front_matter: Option<&FrontMatter>,
+    doc_ctx: &DocumentCtx,
+) -> gtk::Box {
+    let block_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    match (nodes, front_matter) {
+        ([Node::Yaml(_) | Node::Toml(_)], Some(front_matter)) => {
+            front_matter::append_front_matter(front_matter, &block_box, doc_ctx)
+        }
+        _ => append_widgets_from_children(
+            nodes,
+            &block_box,
+            None,
+            doc_ctx,
+            &mut ListContext::default(),
+            None,
+        ),
+    }
+    block_box
+}
+
+fn remove_widgets(content_box: &gtk::Box, block: &Block) {
+    for widget in &block.widgets {
+        content_box.remove(widget);
+    }
+}
+
+/// Source of consecutive nodes, from the start of the first one to the end of the last one.
+fn block_source(input: &str, nodes: &[Node]) -> Option<String> {
+    let start = nodes.first()?.position()?.start.offset;
+    let end = nodes.last()?.position()?.end.offset;
+    input.get(start..end).map(str::to_string)
+}
+
+/// Source that the rendering of every block depends on: the front matter and the link
+/// definitions.
+fn shared_source(children: &[Node], definitions: &HashMap<String, &Definition>) -> String {
+    let mut source = match children.first() {
+        Some(Node::Yaml(yaml)) => yaml.value.clone(),
+        Some(Node::Toml(toml)) => toml.value.clone(),
+        _ => String::new(),
+    };
+    let mut identifiers: Vec<&String> = definitions.keys().collect();
+    identifiers.sort();
+    for identifier in identifiers {
+        let definition = definitions[identifier];
+        source.push_str(&format!(
+            "\n[{identifier}]: {} {:?}",
+            definition.url, definition.title
+        ));
+    }
+    source
+}
@@ -0,0 +1,164 @@
+//! Meta string of code fences, such as `title="main.rs" {3,7-9} showLineNumbers`.
+
+use std::ops::{Range, RangeInclusive};
+
+/// Options set by the meta string of a code fence, after its language.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct CodeMeta {
+    /// File name displayed in a header above the code
+    pub(crate) title: Option<String>,
+    /// Ranges of line numbers, starting at 1, with an emphasized background
+    pub(crate) highlighted_lines: Vec<RangeInclusive<usize>>,
+    pub(crate) show_line_numbers: bool,
+}
+
+impl CodeMeta {
+    /// Parse a meta string made of `title="…"`, `{…}` line ranges and `showLineNumbers`, separated
+    /// by spaces.
+    ///
+    /// ## Logging
+    ///
+    /// A warning log is emitted for each unsupported or invalid part, which is ignored.
+    pub(crate) fn parse(meta: &str) -> Self {
+        let mut code_meta = Self::default();
+        let mut rest = meta.trim_start();
+        while !rest.is_empty() {
+            let (token, after) = match split_token(rest) {
+                Ok(split) => split,
+                Err(err) => {
+                    log::warn!("invalid code fence meta `{meta}`: {err}");
+                    break;
+                }
+            };
+            rest = after.trim_start();
+
+            if token == "showLineNumbers" {
+                code_meta.show_line_numbers = true;
+            } else if let Some(title) = token.strip_prefix("title=") {
+                code_meta.title = Some(unquote(title).to_string());
+            } else if let Some(ranges) = token.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+                for range in ranges.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+                    match parse_line_range(range) {
+                        Some(range) => code_meta.highlighted_lines.push(range),
+                        None => log::warn!("invalid line range in code fence meta: {range}"),
+                    }
+                }
+            } else {
+                log::warn!("unsupported code fence meta: {token}");
+            }
+        }
+        code_meta
+    }
+
+    /// Split the lines of a code block into consecutive ranges of line indexes, each of them
+    /// being either highlighted or not.
+    pub(crate) fn segments(&self, line_count: usize) -> Vec<(Range<usize>, bool)> {
+        let mut segments: Vec<(Range<usize>, bool)> = Vec::new();
+        for index in 0..line_count {
+            let highlighted = self
+                .highlighted_lines
+                .iter()
+                .any(|range| range.contains(&(index + 1)));
+            match segments.last_mut() {
+                Some((range, last_highlighted)) if *last_highlighted == highlighted => {
+                    range.end = index + 1
+                }
+                _ => segments.push((index..index + 1, highlighted)),
+            }
+        }
+        segments
+    }
+}
+
+/// Split the first token off the meta string, keeping quoted values and braces in one token.
+fn split_token(meta: &str) -> Result<(&str, &str), String> {
+    let mut closing: Option<char> = None;
+    for (index, c) in meta.char_indices() {
+        match closing {
+            Some(expected) if c == expected => closing = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => closing = Some(c),
+            None if c == '{' => closing = Some('}'),
+            None if c.is_whitespace() => return Ok((&meta[..index], &meta[index..])),
+            None => {}
+        }
+    }
+    match closing {
+        Some(expected) => Err(format!("missing `{expected}`")),
+        None => Ok((meta, "")),
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .iter()
+        .find_map(|&quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value)
+}
+
+/// Parse a line number or a range of line numbers, such as `3` or `7-9`.
+fn parse_line_range(range: &str) -> Option<RangeInclusive<usize>> {
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let start: usize = start.trim().parse().ok()?;
+    let end: usize = end.trim().parse().ok()?;
+    (1..=end).contains(&start).then_some(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_ranges() {
+        let meta = CodeMeta::parse("{1,3-5}");
+        assert_eq!(meta.highlighted_lines, vec![1..=1, 3..=5]);
+        assert_eq!(
+            meta.segments(6),
+            vec![(0..1, true), (1..2, false), (2..5, true), (5..6, false)]
+        );
+        assert_eq!(
+            CodeMeta::parse("{ 2 , 4 - 4 ,}").highlighted_lines,
+            vec![2..=2, 4..=4]
+        );
+    }
+
+    #[test]
+    fn reversed_and_empty_ranges_are_ignored() {
+        assert!(CodeMeta::parse("{5-3}").highlighted_lines.is_empty());
+        assert!(CodeMeta::parse("{}").highlighted_lines.is_empty());
+        assert!(CodeMeta::parse("{0,-2,x}").highlighted_lines.is_empty());
+        assert_eq!(CodeMeta::parse("{5-3,7}").highlighted_lines, vec![7..=7]);
+        assert_eq!(CodeMeta::parse("").segments(2), vec![(0..2, false)]);
+        assert!(CodeMeta::default().segments(0).is_empty());
+    }
+
+    #[test]
+    fn titles() {
+        let meta = CodeMeta::parse(r#"title="a b" showLineNumbers"#);
+        assert_eq!(meta.title.as_deref(), Some("a b"));
+        assert!(meta.show_line_numbers);
+        assert_eq!(
+            CodeMeta::parse("title='main.rs'").title.as_deref(),
+            Some("main.rs")
+        );
+        assert_eq!(
+            CodeMeta::parse("title=main.rs").title.as_deref(),
+            Some("main.rs")
+        );
+        // An unterminated quote stops the parsing
+        assert_eq!(CodeMeta::parse(r#"title="a b"#), CodeMeta::default());
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let meta = CodeMeta::parse("foo=bar {2} linenos showLineNumbers");
+        assert_eq!(
+            meta,
+            CodeMeta {
+                title: None,
+                highlighted_lines: vec![2..=2],
+                show_line_numbers: true,
+            }
+        );
+    }
+}
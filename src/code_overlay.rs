@@ -0,0 +1,89 @@
+//! Copy button and language badge overlaid on code blocks.
+
+use std::{fmt, rc::Rc, time::Duration};
+
+use gtk::{glib, prelude::*};
+
+use crate::RenderConfig;
+
+/// How long the copy button shows that the code was copied.
+const COPIED_FEEDBACK_DURATION: Duration = Duration::from_millis(1500);
+
+/// Hook called with the code copied by the copy button of a code block.
+#[derive(Clone)]
+pub(crate) struct CopyHook(pub(crate) Rc<dyn Fn(&str)>);
+
+impl fmt::Debug for CopyHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CopyHook")
+    }
+}
+
+/// Overlay the copy button and the language badge enabled in the render configuration on the
+/// body of a code block.
+///
+/// The badge is left out if the block has a header, which already shows the language.
+pub(crate) fn code_overlay(
+    body: &impl IsA<gtk::Widget>,
+    language_name: Option<&str>,
+    has_header: bool,
+    content: &str,
+    render_config: &RenderConfig,
+) -> gtk::Overlay {
+    let overlay = gtk::Overlay::builder().child(body).build();
+    let actions = gtk::Box::builder()
+        .spacing(6)
+        .halign(gtk::Align::End)
+        .valign(gtk::Align::Start)
+        .margin_top(4)
+        .margin_end(4)
+        .build();
+    match language_name {
+        Some(language_name) if render_config.code_language_badge && !has_header => {
+            actions.append(&language_badge(language_name));
+        }
+        _ => {}
+    }
+    if render_config.code_copy_button {
+        actions.append(&copy_button(content, render_config.on_code_copied.clone()));
+    }
+    if actions.first_child().is_some() {
+        overlay.add_overlay(&actions);
+    }
+    overlay
+}
+
+/// Label showing the language of a code block.
+pub(crate) fn language_badge(language_name: &str) -> gtk::Label {
+    gtk::Label::builder()
+        .label(language_name)
+        .valign(gtk::Align::Center)
+        .css_classes(vec!["code_block_language", "dim-label", "caption"])
+        .build()
+}
+
+/// Button copying the code to the clipboard, showing a check mark for a moment once copied.
+fn copy_button(content: &str, on_code_copied: Option<CopyHook>) -> gtk::Button {
+    let button = gtk::Button::builder()
+        .icon_name("edit-copy-symbolic")
+        .tooltip_text("Copy")
+        .css_classes(vec!["flat", "code_block_copy_button"])
+        .build();
+    let content = content.to_string();
+    button.connect_clicked(move |button| {
+        button.clipboard().set_text(&content);
+        button.set_icon_name("object-select-symbolic");
+        button.set_tooltip_text(Some("Copied"));
+        let button = button.downgrade();
+        glib::timeout_add_local_once(COPIED_FEEDBACK_DURATION, move || {
+            if let Some(button) = button.upgrade() {
+                button.set_icon_name("edit-copy-symbolic");
+                button.set_tooltip_text(Some("Copy"));
+            }
+        });
+        if let Some(CopyHook(hook)) = &on_code_copied {
+            hook(&content);
+        }
+    });
+    button
+}
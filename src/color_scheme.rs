@@ -0,0 +1,181 @@
+//! Light or dark color scheme of the application, and the code blocks highlighted again when it
+//! changes.
+
+use std::{
+    cell::{Cell, RefCell},
+    ops::Range,
+    rc::Rc,
+    sync::Arc,
+};
+
+use gtk::{glib, prelude::*};
+
+use crate::{code_markup, highlight_lines, CodeBlockColors, HighlightContext, RenderConfig};
+
+/// Whether the application uses a dark color scheme, according to `adw::StyleManager` if
+/// libadwaita is initialized and the `adwaita` feature is enabled, or to the
+/// `gtk-application-prefer-dark-theme` setting otherwise.
+pub(crate) fn is_dark() -> bool {
+    #[cfg(feature = "adwaita")]
+    if adw::is_initialized() {
+        return adw::StyleManager::default().is_dark();
+    }
+    gtk::Settings::default().is_some_and(|settings| settings.is_gtk_application_prefer_dark_theme())
+}
+
+/// A code block, and what is needed to highlight it again.
+#[derive(Debug)]
+struct CodeBlock {
+    outer_box: glib::WeakRef<gtk::Box>,
+    /// Labels of the block, with the indexes of their lines
+    labels: Vec<(glib::WeakRef<gtk::Label>, Range<usize>)>,
+    language: Option<String>,
+    content: String,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    highlight_context: Option<Arc<HighlightContext>>,
+    light_theme: String,
+    /// Theme of the dark color scheme, or `None` if the code blocks do not follow the color scheme
+    dark_theme: Option<String>,
+    /// Theme the code blocks are highlighted with
+    current_theme: String,
+    colors: CodeBlockColors,
+    blocks: Vec<CodeBlock>,
+}
+
+impl State {
+    fn theme(&self, dark: bool) -> &str {
+        match &self.dark_theme {
+            Some(dark_theme) if dark => dark_theme,
+            _ => &self.light_theme,
+        }
+    }
+}
+
+/// Code blocks of a document, highlighted again with the theme of the new color scheme when it
+/// changes, without rendering the document again.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CodeBlocks(Rc<RefCell<State>>);
+
+impl CodeBlocks {
+    /// Update the highlight context and themes before rendering code blocks, and return the name of
+    /// the theme of the current color scheme.
+    pub(crate) fn configure(&self, render_config: &RenderConfig) -> String {
+        let mut state = self.0.borrow_mut();
+        state.highlight_context = Some(render_config.highlight_context());
+        state.light_theme = render_config.highlight_theme().to_string();
+        state.dark_theme = render_config.dark_highlight_theme().map(str::to_string);
+        state.colors = render_config.code_block_colors();
+        state.current_theme = state.theme(is_dark()).to_string();
+        state
+            .blocks
+            .retain(|block| block.outer_box.upgrade().is_some());
+        state.current_theme.clone()
+    }
+
+    /// Keep track of a code block, if the code blocks follow the color scheme.
+    pub(crate) fn register(
+        &self,
+        outer_box: &gtk::Box,
+        labels: Vec<(gtk::Label, Range<usize>)>,
+        language: Option<&str>,
+        content: &str,
+    ) {
+        let mut state = self.0.borrow_mut();
+        if state.dark_theme.is_none() {
+            return;
+        }
+        state.blocks.push(CodeBlock {
+            outer_box: outer_box.downgrade(),
+            labels: labels
+                .into_iter()
+                .map(|(label, range)| (label.downgrade(), range))
+                .collect(),
+            language: language.map(str::to_string),
+            content: content.to_string(),
+        });
+    }
+
+    /// Highlight the code blocks again if the theme of the color scheme is not the current one.
+    fn recolor(&self, dark: bool) {
+        let mut state = self.0.borrow_mut();
+        let theme = state.theme(dark).to_string();
+        if theme == state.current_theme {
+            return;
+        }
+        let Some(highlight_context) = state.highlight_context.clone() else {
+            return;
+        };
+        state
+            .blocks
+            .retain(|block| block.outer_box.upgrade().is_some());
+        let css_classes = (state.colors == CodeBlockColors::Theme).then(|| {
+            (
+                highlight_context.theme_css_class(&state.current_theme),
+                highlight_context.theme_css_class(&theme),
+            )
+        });
+        for block in &state.blocks {
+            if let (Some((previous, current)), Some(outer_box)) =
+                (&css_classes, block.outer_box.upgrade())
+            {
+                outer_box.remove_css_class(previous);
+                outer_box.add_css_class(current);
+            }
+            let lines = highlight_lines(
+                block.language.as_deref(),
+                &highlight_context,
+                &theme,
+                &block.content,
+            );
+            for (label, range) in &block.labels {
+                if let Some(label) = label.upgrade() {
+                    label.set_markup(&code_markup(&lines[range.clone()]));
+                }
+            }
+        }
+        state.current_theme = theme;
+    }
+
+    /// Highlight the code blocks again when the color scheme changes, until the widget is
+    /// destroyed.
+    pub(crate) fn follow_color_scheme(&self, widget: &impl IsA<gtk::Widget>) {
+        let code_blocks = Rc::downgrade(&self.0);
+        let recolor = move |dark: bool| {
+            if let Some(state) = code_blocks.upgrade() {
+                CodeBlocks(state).recolor(dark);
+            }
+        };
+
+        #[cfg(feature = "adwaita")]
+        if adw::is_initialized() {
+            let style_manager = adw::StyleManager::default();
+            let handler = style_manager
+                .connect_dark_notify(move |style_manager| recolor(style_manager.is_dark()));
+            disconnect_on_destroy(widget, style_manager, handler);
+            return;
+        }
+        let Some(settings) = gtk::Settings::default() else {
+            return;
+        };
+        let handler = settings.connect_gtk_application_prefer_dark_theme_notify(move |settings| {
+            recolor(settings.is_gtk_application_prefer_dark_theme())
+        });
+        disconnect_on_destroy(widget, settings, handler);
+    }
+}
+
+fn disconnect_on_destroy(
+    widget: &impl IsA<gtk::Widget>,
+    object: impl IsA<glib::Object>,
+    handler: glib::SignalHandlerId,
+) {
+    let handler = Cell::new(Some(handler));
+    widget.connect_destroy(move |_| {
+        if let Some(handler) = handler.take() {
+            object.disconnect(handler);
+        }
+    });
+}
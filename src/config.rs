@@ -0,0 +1,620 @@
+//! Render configuration and its builder.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+    rc::Rc,
+    sync::Arc,
+};
+
+use anyhow::bail;
+use gtk::{gio, glib, prelude::*};
+use markdown::ParseOptions;
+
+use crate::{
+    code_overlay::CopyHook,
+    front_matter::FrontMatterSetting,
+    html::SUPPORTED_INLINE_HTML_TAGS,
+    images::ImageLoader,
+    links::{LinkActivation, LinkHook, LinkPolicy},
+};
+
+#[derive(Debug, Clone)]
+pub enum ImageSetting {
+    /// Do not show images
+    Ignore,
+    /// Show images from their path on disk, or from `file://` and `resource://` URIs.
+    ///
+    /// Remote images are not shown, as they would be downloaded on the main thread: use
+    /// [`ImageSetting::Loader`] instead.
+    FromPath,
+    /// Show images from bytes embedded at compilation time, by URL as written in the input. A
+    /// leading `./` is ignored, both in the URLs and in the keys of the map.
+    ///
+    /// See [`include_images!`](crate::include_images) to build the map. Images missing from the
+    /// map are not shown.
+    IncludeBytes(HashMap<String, glib::Bytes>),
+    /// Show images loaded asynchronously by a loader, with a placeholder until they are loaded.
+    ///
+    /// See [`ImageLoaders`](crate::ImageLoaders) for the loaders shipped with this crate.
+    Loader(Rc<dyn ImageLoader>),
+}
+
+/// Numbering style of ordered list items.
+///
+/// Lists with numbers that cannot be represented in a style, such as a list of letters starting
+/// at 0, are numbered in decimal instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListNumbering {
+    /// 1. 2. 3.
+    #[default]
+    Decimal,
+    /// a. b. c.
+    LowerAlpha,
+    /// i. ii. iii.
+    LowerRoman,
+}
+
+impl ListNumbering {
+    /// Style of a list with the given numbers: this style if it can represent all of them,
+    /// decimal otherwise, so that the markers of a list do not mix styles.
+    pub(crate) fn for_list(self, numbers: RangeInclusive<u32>) -> ListNumbering {
+        let represents = |number| match self {
+            ListNumbering::Decimal => true,
+            ListNumbering::LowerAlpha => number > 0,
+            ListNumbering::LowerRoman => number > 0 && number < 4000,
+        };
+        if represents(*numbers.start()) && represents(*numbers.end()) {
+            self
+        } else {
+            ListNumbering::Decimal
+        }
+    }
+
+    /// Format the item marker of the given number, falling back to decimal for numbers that
+    /// cannot be represented in the style.
+    pub(crate) fn format(self, number: u32) -> String {
+        match self {
+            ListNumbering::LowerAlpha if number > 0 => {
+                let mut letters = Vec::new();
+                let mut n = number;
+                while n > 0 {
+                    n -= 1;
+                    letters.push((b'a' + (n % 26) as u8) as char);
+                    n /= 26;
+                }
+                format!("{}.", letters.iter().rev().collect::<String>())
+            }
+            ListNumbering::LowerRoman if number > 0 && number < 4000 => {
+                const NUMERALS: [(u32, &str); 13] = [
+                    (1000, "m"),
+                    (900, "cm"),
+                    (500, "d"),
+                    (400, "cd"),
+                    (100, "c"),
+                    (90, "xc"),
+                    (50, "l"),
+                    (40, "xl"),
+                    (10, "x"),
+                    (9, "ix"),
+                    (5, "v"),
+                    (4, "iv"),
+                    (1, "i"),
+                ];
+                let mut roman = String::new();
+                let mut n = number;
+                for (value, numeral) in NUMERALS {
+                    while n >= value {
+                        roman.push_str(numeral);
+                        n -= value;
+                    }
+                }
+                format!("{roman}.")
+            }
+            _ => format!("{number}."),
+        }
+    }
+}
+
+/// Colors of code blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodeBlockColors {
+    /// Background from the GTK theme, with the highlight theme only coloring the code
+    #[default]
+    Gtk,
+    /// Background, text, selection, gutter and highlighted line colors from the highlight theme
+    Theme,
+}
+
+/// Render configuration options.
+///
+/// Default implementation uses the "base16-mocha.dark" theme for code highlighting
+/// and a parser for the Github flavored Markdown.
+///
+/// Use [`RenderConfig::builder`] to customize it.
+#[derive(Debug)]
+pub struct RenderConfig<'a> {
+    pub(crate) image_settings: ImageSetting,
+    /// Configuration that describes how to parse markdown
+    pub(crate) parse_options: ParseOptions,
+    /// For available themes, please refer to the [syntect](https://github.com/trishume/syntect) documentation.
+    pub(crate) highlight_theme: Cow<'a, str>,
+    /// Theme used instead of `highlight_theme` when the color scheme is dark, if any
+    pub(crate) dark_highlight_theme: Option<Cow<'a, str>>,
+    /// Whether code blocks use the colors of the GTK theme or of the highlight theme
+    pub(crate) code_block_colors: CodeBlockColors,
+    /// Whether code blocks have a button copying their code to the clipboard
+    pub(crate) code_copy_button: bool,
+    /// Whether code blocks show their language
+    pub(crate) code_language_badge: bool,
+    /// Hook called with the code copied by the copy button of a code block
+    pub(crate) on_code_copied: Option<CopyHook>,
+    /// Whether code lines scroll horizontally instead of wrapping
+    pub(crate) code_block_scrolling: bool,
+    /// Height above which code blocks are collapsed, in pixels
+    pub(crate) code_block_max_height: Option<i32>,
+    /// Numbering style of ordered lists for each nesting level, cycling when lists are nested deeper
+    pub(crate) list_numbering: Vec<ListNumbering>,
+    /// Inline HTML tags converted to Pango markup, other tags being escaped as text
+    pub(crate) inline_html_tags: HashSet<String>,
+    pub(crate) front_matter: FrontMatterSetting,
+    /// Whether to display the front matter title as the document header
+    pub(crate) promote_title: bool,
+    /// Whether to alternate the background of table body rows
+    pub(crate) table_striping: bool,
+    /// Directory against which relative image sources and links are resolved
+    pub(crate) base: Option<gio::File>,
+    /// Whether to refuse image sources and links outside of the base directory
+    pub(crate) sandbox: bool,
+    /// Hook called when a link is activated, before the default handling
+    pub(crate) on_link_activated: Option<LinkHook>,
+    /// How links are opened when they are not handled by the hook
+    pub(crate) link_policy: LinkPolicy,
+    /// Syntax definitions and themes, or `None` to use the shared default ones
+    pub(crate) highlight_context: Option<Arc<HighlightContext>>,
+}
+
+impl Default for RenderConfig<'_> {
+    fn default() -> Self {
+        Self {
+            image_settings: ImageSetting::FromPath,
+            parse_options: ParseOptions::gfm(),
+            highlight_theme: Cow::Borrowed("base16-mocha.dark"),
+            dark_highlight_theme: None,
+            code_block_colors: CodeBlockColors::Gtk,
+            code_copy_button: true,
+            code_language_badge: true,
+            on_code_copied: None,
+            code_block_scrolling: false,
+            code_block_max_height: None,
+            list_numbering: vec![
+                ListNumbering::Decimal,
+                ListNumbering::LowerAlpha,
+                ListNumbering::LowerRoman,
+            ],
+            inline_html_tags: SUPPORTED_INLINE_HTML_TAGS
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect(),
+            front_matter: FrontMatterSetting::Hide,
+            promote_title: false,
+            table_striping: false,
+            base: None,
+            sandbox: false,
+            on_link_activated: None,
+            link_policy: LinkPolicy::default(),
+            highlight_context: None,
+        }
+    }
+}
+
+impl<'a> RenderConfig<'a> {
+    /// Create a builder starting from the default configuration.
+    pub fn builder() -> RenderConfigBuilder<'a> {
+        RenderConfigBuilder {
+            config: RenderConfig::default(),
+        }
+    }
+
+    /// How images are displayed.
+    pub fn image_settings(&self) -> &ImageSetting {
+        &self.image_settings
+    }
+
+    /// Configuration that describes how to parse markdown.
+    pub fn parse_options(&self) -> &ParseOptions {
+        &self.parse_options
+    }
+
+    /// Name of the syntect theme used for code highlighting.
+    pub fn highlight_theme(&self) -> &str {
+        &self.highlight_theme
+    }
+
+    /// Name of the syntect theme used for code highlighting when the color scheme is dark, if it
+    /// differs from the highlight theme.
+    pub fn dark_highlight_theme(&self) -> Option<&str> {
+        self.dark_highlight_theme.as_deref()
+    }
+
+    /// Whether code blocks use the colors of the GTK theme or of the highlight theme.
+    pub fn code_block_colors(&self) -> CodeBlockColors {
+        self.code_block_colors
+    }
+
+    /// Whether code blocks have a button copying their code to the clipboard.
+    pub fn code_copy_button(&self) -> bool {
+        self.code_copy_button
+    }
+
+    /// Whether code blocks show their language.
+    pub fn code_language_badge(&self) -> bool {
+        self.code_language_badge
+    }
+
+    /// Whether code lines scroll horizontally instead of wrapping.
+    pub fn code_block_scrolling(&self) -> bool {
+        self.code_block_scrolling
+    }
+
+    /// Height above which code blocks are collapsed, in pixels.
+    pub fn code_block_max_height(&self) -> Option<i32> {
+        self.code_block_max_height
+    }
+
+    /// Numbering style of ordered lists for each nesting level.
+    pub fn list_numbering(&self) -> &[ListNumbering] {
+        &self.list_numbering
+    }
+
+    /// Inline HTML tags converted to Pango markup.
+    pub fn inline_html_tags(&self) -> &HashSet<String> {
+        &self.inline_html_tags
+    }
+
+    /// How front matter is displayed.
+    pub fn front_matter(&self) -> FrontMatterSetting {
+        self.front_matter
+    }
+
+    /// Whether the front matter title is displayed as the document header.
+    pub fn promote_title(&self) -> bool {
+        self.promote_title
+    }
+
+    /// Whether the background of table body rows alternates.
+    pub fn table_striping(&self) -> bool {
+        self.table_striping
+    }
+
+    /// Directory against which relative image sources and links are resolved.
+    pub fn base(&self) -> Option<&gio::File> {
+        self.base.as_ref()
+    }
+
+    /// Whether image sources and links outside of the base directory are refused.
+    pub fn sandbox(&self) -> bool {
+        self.sandbox
+    }
+
+    /// How links are opened when they are not handled by the hook.
+    pub fn link_policy(&self) -> &LinkPolicy {
+        &self.link_policy
+    }
+
+    /// Syntax definitions and themes used to highlight code blocks.
+    pub fn highlight_context(&self) -> Arc<HighlightContext> {
+        self.highlight_context
+            .clone()
+            .unwrap_or_else(HighlightContext::shared)
+    }
+}
+
+/// Names of the default themes that can be passed to [`RenderConfigBuilder::highlight_theme`].
+pub fn available_highlight_themes() -> Vec<String> {
+    HighlightContext::shared()
+        .theme_set()
+        .themes
+        .keys()
+        .cloned()
+        .collect()
+}
+
+/// Builder for [`RenderConfig`], validating the configuration when calling [`RenderConfigBuilder::build`].
+///
+/// ## Example
+///
+/// ```rust
+/// use gtk4_commonmark::{ImageSetting, ParseOptions, RenderConfig};
+///
+/// let config = RenderConfig::builder()
+///     .highlight_theme("InspiredGitHub")
+///     .parse_options(ParseOptions::default())
+///     .image_settings(ImageSetting::Ignore)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RenderConfigBuilder<'a> {
+    config: RenderConfig<'a>,
+}
+
+impl<'a> RenderConfigBuilder<'a> {
+    /// Set how images are displayed.
+    pub fn image_settings(mut self, image_settings: ImageSetting) -> Self {
+        self.config.image_settings = image_settings;
+        self
+    }
+
+    /// Set the configuration that describes how to parse markdown.
+    pub fn parse_options(mut self, parse_options: ParseOptions) -> Self {
+        self.config.parse_options = parse_options;
+        self
+    }
+
+    /// Set the syntect theme used for code highlighting.
+    ///
+    /// See [`available_highlight_themes`] for the accepted names.
+    pub fn highlight_theme(mut self, highlight_theme: &'a str) -> Self {
+        self.config.highlight_theme = Cow::Borrowed(highlight_theme);
+        self
+    }
+
+    /// Set the syntect themes used for code highlighting with the light and the dark color
+    /// schemes.
+    ///
+    /// The color scheme is the one of `adw::StyleManager` if libadwaita is initialized and the
+    /// `adwaita` feature is enabled, or the `gtk-application-prefer-dark-theme` setting otherwise.
+    /// Code blocks are highlighted again when it changes, without rendering the document again.
+    pub fn highlight_themes(mut self, light: &'a str, dark: &'a str) -> Self {
+        self.config.highlight_theme = Cow::Borrowed(light);
+        self.config.dark_highlight_theme = Some(Cow::Borrowed(dark));
+        self
+    }
+
+    /// Set whether code blocks use the colors of the GTK theme, which is the default, or the
+    /// background, text, selection, gutter and highlighted line colors of the highlight theme.
+    ///
+    /// Highlight themes are made for their own background, so using their colors keeps the code
+    /// readable with any GTK theme.
+    pub fn code_block_colors(mut self, code_block_colors: CodeBlockColors) -> Self {
+        self.config.code_block_colors = code_block_colors;
+        self
+    }
+
+    /// Set whether code blocks have a button copying their code to the clipboard, which is the
+    /// default. The button shows a check mark for a moment once the code is copied.
+    pub fn code_copy_button(mut self, code_copy_button: bool) -> Self {
+        self.config.code_copy_button = code_copy_button;
+        self
+    }
+
+    /// Set whether code blocks show their language as a badge, or in their header if they have a
+    /// title, which is the default.
+    pub fn code_language_badge(mut self, code_language_badge: bool) -> Self {
+        self.config.code_language_badge = code_language_badge;
+        self
+    }
+
+    /// Set a hook called with the code copied by the copy button of a code block, such as to show
+    /// an `adw::Toast`.
+    pub fn on_code_copied(mut self, hook: impl Fn(&str) + 'static) -> Self {
+        self.config.on_code_copied = Some(CopyHook(Rc::new(hook)));
+        self
+    }
+
+    /// Set whether code blocks scroll horizontally instead of wrapping their lines, which keeps
+    /// their indentation readable.
+    ///
+    /// Code blocks with line numbers always scroll horizontally.
+    pub fn code_block_scrolling(mut self, code_block_scrolling: bool) -> Self {
+        self.config.code_block_scrolling = code_block_scrolling;
+        self
+    }
+
+    /// Set the height, in pixels, above which code blocks are collapsed to that height, with a
+    /// "Show more" expander showing them whole.
+    pub fn code_block_max_height(mut self, code_block_max_height: i32) -> Self {
+        self.config.code_block_max_height = Some(code_block_max_height);
+        self
+    }
+
+    /// Set the numbering style of ordered lists for each nesting level, starting with top-level lists.
+    ///
+    /// Styles are cycled through when lists are nested deeper than the number of given styles.
+    pub fn list_numbering(mut self, list_numbering: Vec<ListNumbering>) -> Self {
+        self.config.list_numbering = list_numbering;
+        self
+    }
+
+    /// Set the inline HTML tags, such as `kbd` or `sup`, that are converted to Pango markup.
+    ///
+    /// Other tags are escaped and displayed as text. By default, all the
+    /// [supported tags](SUPPORTED_INLINE_HTML_TAGS) are allowed.
+    pub fn inline_html_tags<I, S>(mut self, inline_html_tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.inline_html_tags = inline_html_tags
+            .into_iter()
+            .map(|tag| tag.into().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Set how front matter is displayed.
+    ///
+    /// Front matter is only parsed if the `frontmatter` construct is enabled in the parse options.
+    pub fn front_matter(mut self, front_matter: FrontMatterSetting) -> Self {
+        self.config.front_matter = front_matter;
+        self
+    }
+
+    /// Set whether the `title` field of the front matter is displayed as the document header.
+    pub fn promote_title(mut self, promote_title: bool) -> Self {
+        self.config.promote_title = promote_title;
+        self
+    }
+
+    /// Set whether the background of table body rows alternates, to keep wide tables readable.
+    pub fn table_striping(mut self, table_striping: bool) -> Self {
+        self.config.table_striping = table_striping;
+        self
+    }
+
+    /// Set the directory against which relative image sources and links are resolved, usually the
+    /// directory containing the markdown file. It can be any `gio::File`, such as a GResource
+    /// directory.
+    ///
+    /// Without a base, relative paths are resolved against the working directory.
+    pub fn base(mut self, base: &impl IsA<gio::File>) -> Self {
+        self.config.base = Some(base.clone().upcast());
+        self
+    }
+
+    /// Set whether image sources and links outside of the base directory, such as `../../etc`
+    /// or absolute paths, are refused. Refused images are not shown, and refused links are
+    /// rendered as plain text.
+    ///
+    /// Paths are compared without following symbolic links. URLs with a scheme other than `file`
+    /// are not restricted.
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.config.sandbox = sandbox;
+        self
+    }
+
+    /// Set a hook called when a link is activated, with its URL and text.
+    ///
+    /// Marking the link as handled prevents the default handling: `#anchor` links scroll to their
+    /// anchor, and other links are opened according to the link policy.
+    pub fn on_link_activated(mut self, hook: impl Fn(&LinkActivation) + 'static) -> Self {
+        self.config.on_link_activated = Some(LinkHook(Rc::new(hook)));
+        self
+    }
+
+    /// Set how links are opened when they are not handled by the hook.
+    pub fn link_policy(mut self, link_policy: LinkPolicy) -> Self {
+        self.config.link_policy = link_policy;
+        self
+    }
+
+    /// Set the syntax definitions and themes used to highlight code blocks, instead of the shared
+    /// default ones.
+    pub fn highlight_context(mut self, highlight_context: Arc<HighlightContext>) -> Self {
+        self.config.highlight_context = Some(highlight_context);
+        self
+    }
+
+    /// Validate and return the configuration.
+    ///
+    /// ## Errors
+    ///
+    /// An error is returned if:
+    ///
+    /// - the highlight theme or the dark highlight theme is not in the highlight context
+    /// - the maximum height of code blocks is not positive
+    /// - no list numbering style is given
+    /// - an inline HTML tag is not supported
+    /// - the parse options enable constructs that conflict with each other, or a construct without
+    ///   the construct it depends on
+    /// - the sandbox is enabled without a base directory
+    pub fn build(self) -> anyhow::Result<RenderConfig<'a>> {
+        let highlight_context = self.config.highlight_context();
+        let themes = &highlight_context.theme_set().themes;
+        if let Some(theme) = std::iter::once(&self.config.highlight_theme)
+            .chain(&self.config.dark_highlight_theme)
+            .find(|theme| !themes.contains_key(theme.as_ref()))
+        {
+            bail!("unknown theme name: {theme}");
+        }
+        if let Some(max_height) = self.config.code_block_max_height.filter(|&h| h <= 0) {
+            bail!("the maximum height of code blocks must be positive, got {max_height}");
+        }
+        if self.config.list_numbering.is_empty() {
+            bail!("at least one list numbering style is required");
+        }
+        if let Some(tag) = self
+            .config
+            .inline_html_tags
+            .iter()
+            .find(|tag| !SUPPORTED_INLINE_HTML_TAGS.contains(&tag.as_str()))
+        {
+            bail!("unsupported inline HTML tag: {tag}");
+        }
+        validate_parse_options(&self.config.parse_options)?;
+        if self.config.sandbox && self.config.base.is_none() {
+            bail!("the sandbox requires a base directory");
+        }
+
+        Ok(self.config)
+    }
+}
+
+/// Check that the enabled constructs can work together.
+fn validate_parse_options(parse_options: &ParseOptions) -> anyhow::Result<()> {
+    let constructs = &parse_options.constructs;
+    if constructs.html_flow && constructs.mdx_jsx_flow {
+        bail!("the `html_flow` and `mdx_jsx_flow` constructs cannot be enabled together");
+    }
+    if constructs.html_text && constructs.mdx_jsx_text {
+        bail!("the `html_text` and `mdx_jsx_text` constructs cannot be enabled together");
+    }
+    if (constructs.label_start_link || constructs.label_start_image) && !constructs.label_end {
+        bail!("the `label_start_link` and `label_start_image` constructs require `label_end`");
+    }
+    if constructs.gfm_footnote_definition && !constructs.gfm_label_start_footnote {
+        bail!("the `gfm_footnote_definition` construct requires `gfm_label_start_footnote`");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alphabetic_numbering_rolls_over() {
+        let format = |number| ListNumbering::LowerAlpha.format(number);
+        assert_eq!(format(1), "a.");
+        assert_eq!(format(26), "z.");
+        assert_eq!(format(27), "aa.");
+        assert_eq!(format(52), "az.");
+        assert_eq!(format(53), "ba.");
+        assert_eq!(format(702), "zz.");
+        assert_eq!(format(703), "aaa.");
+    }
+
+    #[test]
+    fn roman_numbering() {
+        let format = |number| ListNumbering::LowerRoman.format(number);
+        assert_eq!(format(1), "i.");
+        assert_eq!(format(4), "iv.");
+        assert_eq!(format(9), "ix.");
+        assert_eq!(format(14), "xiv.");
+        assert_eq!(format(40), "xl.");
+        assert_eq!(format(90), "xc.");
+        assert_eq!(format(400), "cd.");
+        assert_eq!(format(1994), "mcmxciv.");
+        assert_eq!(format(3999), "mmmcmxcix.");
+        assert_eq!(format(4000), "4000.");
+    }
+
+    #[test]
+    fn lists_starting_at_zero_are_decimal() {
+        for numbering in [ListNumbering::LowerAlpha, ListNumbering::LowerRoman] {
+            assert_eq!(numbering.for_list(0..=3), ListNumbering::Decimal);
+            assert_eq!(numbering.for_list(1..=3), numbering);
+        }
+        assert_eq!(
+            ListNumbering::LowerRoman.for_list(3998..=4001),
+            ListNumbering::Decimal
+        );
+        assert_eq!(
+            ListNumbering::Decimal.for_list(0..=3),
+            ListNumbering::Decimal
+        );
+    }
+}
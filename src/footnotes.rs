@@ -0,0 +1,247 @@
+//! GFM footnote references and the footnotes section appended at the end of the document.
+
+use std::collections::HashMap;
+
+use gtk::{prelude::*, EventControllerMotion};
+use html2pango::html_escape;
+use markdown::mdast::{FootnoteDefinition, Node};
+
+use crate::{
+    append_widgets_from_children, label_append, links, normalize_identifier, DocumentCtx,
+    ListContext,
+};
+
+/// Footnote definitions of the document, and the references rendered so far.
+#[derive(Default)]
+pub(crate) struct Footnotes<'a> {
+    /// Definitions by normalized identifier, collected before rendering
+    definitions: HashMap<String, &'a FootnoteDefinition>,
+    /// Identifiers of referenced footnotes, in order of first use
+    referenced: Vec<String>,
+    /// Labels containing at least one footnote reference
+    labels: Vec<gtk::Label>,
+}
+
+impl<'a> Footnotes<'a> {
+    /// Collect the footnote definitions of the whole tree.
+    pub(crate) fn collect(nodes: &'a [Node]) -> Self {
+        let mut footnotes = Self::default();
+        footnotes.collect_definitions(nodes);
+        footnotes
+    }
+
+    /// Whether the document has no footnote definitions.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    fn collect_definitions(&mut self, nodes: &'a [Node]) {
+        for node in nodes {
+            if let Node::FootnoteDefinition(definition) = node {
+                self.definitions
+                    .entry(normalize_identifier(&definition.identifier))
+                    .or_insert(definition);
+            }
+            if let Some(children) = node.children() {
+                self.collect_definitions(children);
+            }
+        }
+    }
+
+    /// Register a reference found in the given label.
+    ///
+    /// Returns the footnote number, assigned in order of first use, and whether this is the first
+    /// reference to the footnote. Returns `None` if the footnote is not defined.
+    fn reference(&mut self, identifier: &str, label: &gtk::Label) -> Option<(usize, bool)> {
+        self.definitions.get(identifier)?;
+        if !self.labels.contains(label) {
+            self.labels.push(label.clone());
+        }
+        match self.referenced.iter().position(|id| id == identifier) {
+            Some(index) => Some((index + 1, false)),
+            None => {
+                self.referenced.push(identifier.to_string());
+                Some((self.referenced.len(), true))
+            }
+        }
+    }
+}
+
+/// Append a footnote reference to a label, as a superscript link to its definition.
+pub(crate) fn append_reference(
+    label: &gtk::Label,
+    identifier: &str,
+    source_label: Option<&str>,
+    doc_ctx: &DocumentCtx,
+) {
+    let identifier = normalize_identifier(identifier);
+    let reference = doc_ctx.footnotes.borrow_mut().reference(&identifier, label);
+    match reference {
+        Some((number, first_use)) => {
+            if first_use {
+                doc_ctx.anchors.insert(format!("fnref-{identifier}"), label);
+            }
+            label_append(
+                label,
+                &format!(
+                    "<sup><a href=\"#fn-{}\">[{number}]</a></sup>",
+                    html_escape(&identifier)
+                ),
+            );
+        }
+        None => label_append(
+            label,
+            &html_escape(&format!("[^{}]", source_label.unwrap_or(&identifier))),
+        ),
+    }
+}
+
+/// Append the definitions of the referenced footnotes, in order of first use, to the root `gtk::Box`.
+///
+/// Each definition has a back-link to its first reference, and hovering a reference shows the
+/// footnote text in a popover.
+pub(crate) fn append_footnotes_section(root: &gtk::Box, doc_ctx: &DocumentCtx) {
+    if doc_ctx.footnotes.borrow().referenced.is_empty() {
+        return;
+    }
+
+    let footnotes_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .name("commonmark_footnotes_box")
+        .build();
+    footnotes_box.append(&gtk::Separator::new(gtk::Orientation::Horizontal));
+
+    // Definitions can reference other footnotes, which are then appended to the list
+    let mut index = 0;
+    loop {
+        let identifier = doc_ctx.footnotes.borrow().referenced.get(index).cloned();
+        let Some(identifier) = identifier else {
+            break;
+        };
+        index += 1;
+        let Some(definition) = doc_ctx
+            .footnotes
+            .borrow()
+            .definitions
+            .get(&identifier)
+            .copied()
+        else {
+            continue;
+        };
+
+        let item_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(5)
+            .name("commonmark_footnote_box")
+            .build();
+        item_box.append(
+            &gtk::Label::builder()
+                .label(format!("<small>{index}.</small>"))
+                .use_markup(true)
+                .valign(gtk::Align::Start)
+                .build(),
+        );
+        let content_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(5)
+            .build();
+        append_widgets_from_children(
+            &definition.children,
+            &content_box,
+            None,
+            doc_ctx,
+            &mut ListContext::default(),
+            None,
+        );
+        item_box.append(&content_box);
+
+        let back_link = gtk::Label::builder()
+            .label(format!(
+                "<a href=\"#fnref-{}\">↩</a>",
+                html_escape(&identifier)
+            ))
+            .use_markup(true)
+            .valign(gtk::Align::Start)
+            .tooltip_text("Back to reference")
+            .build();
+        links::connect_label(&back_link, doc_ctx);
+        item_box.append(&back_link);
+
+        doc_ctx
+            .anchors
+            .insert(format!("fn-{identifier}"), &item_box);
+        footnotes_box.append(&item_box);
+    }
+    root.append(&footnotes_box);
+
+    attach_popovers(doc_ctx);
+}
+
+/// Show the text of a footnote in a popover when hovering one of its references.
+fn attach_popovers(doc_ctx: &DocumentCtx) {
+    let footnotes = doc_ctx.footnotes.borrow();
+    let texts: HashMap<String, String> = footnotes
+        .referenced
+        .iter()
+        .filter_map(|identifier| {
+            let definition = footnotes.definitions.get(identifier)?;
+            let text = definition
+                .children
+                .iter()
+                .map(|child| child.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            Some((identifier.clone(), text))
+        })
+        .collect();
+
+    for label in &footnotes.labels {
+        let popover = gtk::Popover::builder()
+            .autohide(false)
+            .can_focus(false)
+            .position(gtk::PositionType::Top)
+            .child(
+                &gtk::Label::builder()
+                    .label(String::new())
+                    .wrap(true)
+                    .max_width_chars(60)
+                    .build(),
+            )
+            .build();
+        popover.set_parent(label);
+        let destroyed_popover = popover.clone();
+        label.connect_destroy(move |_| destroyed_popover.unparent());
+
+        let motion = EventControllerMotion::new();
+        let texts = texts.clone();
+        let hovered_label = label.downgrade();
+        let hovered_popover = popover.clone();
+        motion.connect_motion(move |_, x, y| {
+            let Some(label) = hovered_label.upgrade() else {
+                return;
+            };
+            let text = label
+                .current_uri()
+                .and_then(|uri| uri.strip_prefix("#fn-").map(str::to_string))
+                .and_then(|identifier| texts.get(&identifier));
+            match text {
+                Some(text) => {
+                    if let Some(text_label) = hovered_popover
+                        .child()
+                        .and_then(|child| child.downcast::<gtk::Label>().ok())
+                    {
+                        text_label.set_label(text);
+                    }
+                    hovered_popover
+                        .set_pointing_to(Some(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+                    hovered_popover.popup();
+                }
+                None => hovered_popover.popdown(),
+            }
+        });
+        let left_popover = popover.clone();
+        motion.connect_leave(move |_| left_popover.popdown());
+        label.add_controller(motion);
+    }
+}
@@ -0,0 +1,326 @@
+//! YAML and TOML front matter, parsed as document metadata.
+
+use std::collections::BTreeMap;
+
+use gtk::prelude::*;
+use html2pango::html_escape;
+use markdown::mdast::Node;
+
+use crate::{parse_code_block, DocumentCtx};
+
+/// How front matter is displayed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrontMatterSetting {
+    /// Do not display front matter
+    #[default]
+    Hide,
+    /// Display the title, author, date and tags fields in a metadata card
+    Card,
+    /// Display front matter as a code block
+    Raw,
+}
+
+/// Syntax of the front matter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontMatterFormat {
+    Yaml,
+    Toml,
+}
+
+/// A front matter value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrontMatterValue {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    /// Strings, and dates which are kept as written
+    String(String),
+    List(Vec<FrontMatterValue>),
+    Map(BTreeMap<String, FrontMatterValue>),
+}
+
+impl FrontMatterValue {
+    /// The string value, if this is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FrontMatterValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Text to display for scalar values, or `None` for null values, lists and maps.
+    fn display_text(&self) -> Option<String> {
+        match self {
+            FrontMatterValue::Bool(value) => Some(value.to_string()),
+            FrontMatterValue::Integer(value) => Some(value.to_string()),
+            FrontMatterValue::Float(value) => Some(value.to_string()),
+            FrontMatterValue::String(value) => Some(value.clone()),
+            FrontMatterValue::Null | FrontMatterValue::List(_) | FrontMatterValue::Map(_) => None,
+        }
+    }
+}
+
+impl From<serde_yaml::Value> for FrontMatterValue {
+    fn from(value: serde_yaml::Value) -> Self {
+        match value {
+            serde_yaml::Value::Null => FrontMatterValue::Null,
+            serde_yaml::Value::Bool(value) => FrontMatterValue::Bool(value),
+            serde_yaml::Value::Number(number) => match number.as_i64() {
+                Some(value) => FrontMatterValue::Integer(value),
+                None => FrontMatterValue::Float(number.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_yaml::Value::String(value) => FrontMatterValue::String(value),
+            serde_yaml::Value::Sequence(values) => {
+                FrontMatterValue::List(values.into_iter().map(Into::into).collect())
+            }
+            serde_yaml::Value::Mapping(mapping) => FrontMatterValue::Map(
+                mapping
+                    .into_iter()
+                    .filter_map(|(key, value)| {
+                        let key = FrontMatterValue::from(key).display_text()?;
+                        Some((key, value.into()))
+                    })
+                    .collect(),
+            ),
+            serde_yaml::Value::Tagged(tagged) => tagged.value.into(),
+        }
+    }
+}
+
+impl From<toml::Value> for FrontMatterValue {
+    fn from(value: toml::Value) -> Self {
+        match value {
+            toml::Value::String(value) => FrontMatterValue::String(value),
+            toml::Value::Integer(value) => FrontMatterValue::Integer(value),
+            toml::Value::Float(value) => FrontMatterValue::Float(value),
+            toml::Value::Boolean(value) => FrontMatterValue::Bool(value),
+            toml::Value::Datetime(value) => FrontMatterValue::String(value.to_string()),
+            toml::Value::Array(values) => {
+                FrontMatterValue::List(values.into_iter().map(Into::into).collect())
+            }
+            toml::Value::Table(table) => FrontMatterValue::Map(
+                table
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Front matter of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontMatter {
+    format: FrontMatterFormat,
+    raw: String,
+    fields: BTreeMap<String, FrontMatterValue>,
+}
+
+impl FrontMatter {
+    /// Parse the front matter of a document tree, if it has one.
+    ///
+    /// ## Logging
+    ///
+    /// A warning log is emitted if the front matter cannot be parsed, in which case it has no
+    /// fields.
+    pub(crate) fn from_nodes(nodes: &[Node]) -> Option<Self> {
+        let (format, raw) = match nodes.first()? {
+            Node::Yaml(yaml) => (FrontMatterFormat::Yaml, &yaml.value),
+            Node::Toml(toml) => (FrontMatterFormat::Toml, &toml.value),
+            _ => return None,
+        };
+        let value = match format {
+            FrontMatterFormat::Yaml => serde_yaml::from_str::<serde_yaml::Value>(raw)
+                .map(FrontMatterValue::from)
+                .map_err(anyhow::Error::from),
+            FrontMatterFormat::Toml => toml::from_str::<toml::Table>(raw)
+                .map(|table| FrontMatterValue::from(toml::Value::Table(table)))
+                .map_err(anyhow::Error::from),
+        };
+        let fields = match value {
+            Ok(FrontMatterValue::Map(fields)) => fields,
+            Ok(FrontMatterValue::Null) => BTreeMap::new(),
+            Ok(_) => {
+                log::warn!("front matter is not a map of fields");
+                BTreeMap::new()
+            }
+            Err(err) => {
+                log::warn!("unable to parse front matter: {err}");
+                BTreeMap::new()
+            }
+        };
+
+        Some(Self {
+            format,
+            raw: raw.clone(),
+            fields,
+        })
+    }
+
+    /// Syntax of the front matter.
+    pub fn format(&self) -> FrontMatterFormat {
+        self.format
+    }
+
+    /// Source of the front matter, without its delimiters.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Fields of the front matter. There are none if it could not be parsed.
+    pub fn fields(&self) -> &BTreeMap<String, FrontMatterValue> {
+        &self.fields
+    }
+
+    /// Value of a field.
+    pub fn get(&self, key: &str) -> Option<&FrontMatterValue> {
+        self.fields.get(key)
+    }
+
+    /// The `title` field, if it is a string.
+    pub fn title(&self) -> Option<&str> {
+        self.get("title").and_then(FrontMatterValue::as_str)
+    }
+
+    /// The `tags` field, either a list or a comma-separated string.
+    fn tags(&self) -> Vec<String> {
+        match self.get("tags") {
+            Some(FrontMatterValue::List(tags)) => tags
+                .iter()
+                .filter_map(FrontMatterValue::display_text)
+                .collect(),
+            Some(FrontMatterValue::String(tags)) => tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Create the document header, displaying the title promoted from the front matter.
+fn header_widget(title: &str) -> gtk::Box {
+    let header_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .name("commonmark_document_header")
+        .build();
+    header_box.append(
+        &gtk::Label::builder()
+            .label(format!(
+                "<span font_size=\"xx-large\" font_weight=\"bold\">{}</span>",
+                html_escape(title)
+            ))
+            .use_markup(true)
+            .wrap(true)
+            .halign(gtk::Align::Start)
+            .justify(gtk::Justification::Left)
+            .build(),
+    );
+    header_box.append(
+        &gtk::Separator::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .height_request(4)
+            .build(),
+    );
+    header_box
+}
+
+/// Create a card displaying the title, author, date and tags of the front matter, or `None` if it
+/// has none of them. The title is left out if it is promoted to the document header.
+fn card_widget(front_matter: &FrontMatter, with_title: bool) -> Option<gtk::Box> {
+    let card = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .css_classes(vec!["front_matter_card"])
+        .name("commonmark_front_matter_card")
+        .build();
+    let mut is_empty = true;
+
+    if let Some(title) = front_matter.title().filter(|_| with_title) {
+        card.append(&card_label(&format!(
+            "<span font_size=\"large\" font_weight=\"bold\">{}</span>",
+            html_escape(title)
+        )));
+        is_empty = false;
+    }
+    let byline: Vec<String> = ["author", "date"]
+        .iter()
+        .filter_map(|key| front_matter.get(key)?.display_text())
+        .map(|text| html_escape(&text))
+        .collect();
+    if !byline.is_empty() {
+        card.append(&card_label(&format!("<i>{}</i>", byline.join(" · "))));
+        is_empty = false;
+    }
+    let tags = front_matter.tags();
+    if !tags.is_empty() {
+        let tags_box = gtk::FlowBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .column_spacing(5)
+            .row_spacing(5)
+            .max_children_per_line(30)
+            .build();
+        for tag in tags {
+            tags_box.insert(
+                &gtk::Label::builder()
+                    .label(tag)
+                    .css_classes(vec!["front_matter_tag"])
+                    .build(),
+                -1,
+            );
+        }
+        card.append(&tags_box);
+        is_empty = false;
+    }
+
+    (!is_empty).then_some(card)
+}
+
+fn card_label(markup: &str) -> gtk::Label {
+    gtk::Label::builder()
+        .label(markup)
+        .use_markup(true)
+        .wrap(true)
+        .halign(gtk::Align::Start)
+        .justify(gtk::Justification::Left)
+        .build()
+}
+
+/// Append the front matter to the root `gtk::Box`, according to the render configuration.
+pub(crate) fn append_front_matter(
+    front_matter: &FrontMatter,
+    root: &gtk::Box,
+    doc_ctx: &DocumentCtx,
+) {
+    let render_config = doc_ctx.render_config;
+    let title = front_matter.title().filter(|_| render_config.promote_title);
+    if let Some(title) = title {
+        root.append(&header_widget(title));
+    }
+
+    match render_config.front_matter {
+        FrontMatterSetting::Hide => {}
+        FrontMatterSetting::Card => {
+            if let Some(card) = card_widget(front_matter, title.is_none()) {
+                root.append(&card);
+            }
+        }
+        FrontMatterSetting::Raw => {
+            let language = match front_matter.format {
+                FrontMatterFormat::Yaml => "yaml",
+                FrontMatterFormat::Toml => "toml",
+            };
+            parse_code_block(
+                Some(&language.to_string()),
+                None,
+                &front_matter.raw,
+                root,
+                doc_ctx,
+            );
+        }
+    }
+}
@@ -0,0 +1,400 @@
+//! Syntax definitions and themes used to highlight code blocks, shared between renders.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::JoinHandle,
+};
+
+use anyhow::{bail, Context};
+use gtk::{gdk, gio, glib, glib::once_cell::sync::OnceCell};
+use syntect::{
+    highlighting::{Color, Theme, ThemeSet, ThemeSettings},
+    parsing::{SyntaxDefinition, SyntaxReference, SyntaxSet},
+};
+
+/// Context shared by the renders that use the default syntax definitions and themes.
+static SHARED: OnceCell<Arc<HighlightContext>> = OnceCell::new();
+
+thread_local! {
+    /// CSS classes of the themes whose colors are applied to code blocks by a CSS provider
+    static THEME_CSS_CLASSES: RefCell<HashSet<String>> = RefCell::default();
+}
+
+/// Fence languages that the bundled syntax definitions do not know, and the language they are
+/// highlighted as.
+const DEFAULT_ALIASES: &[(&str, &str)] = &[
+    ("jsonc", "json"),
+    ("json5", "json"),
+    ("sh-session", "bash"),
+    ("shell-session", "bash"),
+    ("shellsession", "bash"),
+    ("console", "bash"),
+    ("shell", "bash"),
+    ("c++", "cpp"),
+    ("golang", "go"),
+];
+
+/// Syntax definitions and themes used to highlight code blocks.
+///
+/// Loading them takes tens of milliseconds, so a context is shared by every render: the default
+/// one with [`HighlightContext::shared`], or a custom one set with
+/// [`RenderConfigBuilder::highlight_context`](crate::RenderConfigBuilder::highlight_context).
+#[derive(Debug)]
+pub struct HighlightContext {
+    syntax_set: SyntaxSet,
+    theme_set: ThemeSet,
+    /// Languages by lowercase alias
+    aliases: HashMap<String, String>,
+}
+
+impl HighlightContext {
+    /// Create a context from pre-built syntax definitions and themes, with the default language
+    /// aliases.
+    ///
+    /// The syntax definitions must be loaded with newlines, as with
+    /// `SyntaxSet::load_defaults_newlines`.
+    pub fn new(syntax_set: SyntaxSet, theme_set: ThemeSet) -> Self {
+        Self {
+            syntax_set,
+            theme_set,
+            aliases: default_aliases(),
+        }
+    }
+
+    /// Create a context from the syntax definitions and themes bundled with syntect.
+    pub fn defaults() -> Self {
+        Self::new(
+            SyntaxSet::load_defaults_newlines(),
+            ThemeSet::load_defaults(),
+        )
+    }
+
+    /// Create a builder adding syntax definitions, themes and language aliases to the ones bundled
+    /// with syntect.
+    pub fn builder() -> HighlightContextBuilder {
+        HighlightContextBuilder::default()
+    }
+
+    /// Context with the syntax definitions and themes bundled with syntect, loaded on first use.
+    pub fn shared() -> Arc<Self> {
+        SHARED.get_or_init(|| Arc::new(Self::defaults())).clone()
+    }
+
+    /// Load the shared context in a background thread, so that the first render does not block
+    /// the main thread while loading it.
+    pub fn warm_up() -> JoinHandle<()> {
+        std::thread::spawn(|| {
+            Self::shared();
+        })
+    }
+
+    /// Syntax definitions of the context.
+    pub fn syntax_set(&self) -> &SyntaxSet {
+        &self.syntax_set
+    }
+
+    /// Themes of the context.
+    pub fn theme_set(&self) -> &ThemeSet {
+        &self.theme_set
+    }
+
+    /// Find the syntax definition of a code fence language, by name, file extension or alias.
+    pub fn find_syntax(&self, language: &str) -> Option<&SyntaxReference> {
+        self.syntax_set.find_syntax_by_token(language).or_else(|| {
+            let aliased = self.aliases.get(&language.to_lowercase())?;
+            self.syntax_set.find_syntax_by_token(aliased)
+        })
+    }
+
+    /// CSS class applying the colors of a theme to a code block.
+    ///
+    /// The CSS provider of a theme is added to the default display the first time its class is
+    /// requested, so themes of different contexts with the same name share their colors.
+    pub(crate) fn theme_css_class(&self, theme_name: &str) -> String {
+        let class: String = theme_name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let class = format!("code_theme_{class}");
+        if !THEME_CSS_CLASSES.with(|classes| classes.borrow_mut().insert(class.clone())) {
+            return class;
+        }
+        let Some(theme) = self.theme_set.themes.get(theme_name) else {
+            return class;
+        };
+
+        let provider = gtk::CssProvider::new();
+        provider.load_from_data(&theme_css(&class, &theme.settings));
+        if let Some(display) = gdk::Display::default() {
+            gtk::StyleContext::add_provider_for_display(
+                &display,
+                &provider,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+            );
+        } else {
+            log::error!("unable to load CSS for theme {theme_name}: could not connect to a display")
+        }
+        class
+    }
+}
+
+impl Default for HighlightContext {
+    fn default() -> Self {
+        Self::defaults()
+    }
+}
+
+/// Where syntax definitions or themes are loaded from.
+#[derive(Debug)]
+enum Source {
+    /// Directory, searched recursively
+    Folder(PathBuf),
+    /// GResource directory
+    Resource(String),
+    /// Content of a single file, with the name of the theme
+    Bytes(Vec<u8>, Option<String>),
+}
+
+/// Builder of a [`HighlightContext`] extending the syntax definitions and themes bundled with
+/// syntect.
+///
+/// The files are only read by [`build`](HighlightContextBuilder::build), which is best done once,
+/// possibly in a background thread, as the syntax definitions must be compiled.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use gtk4_commonmark::{HighlightContext, RenderConfig};
+///
+/// let brand_theme = std::fs::read("brand.tmTheme")?;
+/// let highlight_context = HighlightContext::builder()
+///     .syntaxes_from_resource("/com/example/App/syntaxes")
+///     .theme_from_bytes("Brand", &brand_theme)
+///     .alias("dsl", "yaml")
+///     .build()?;
+/// let config = RenderConfig::builder()
+///     .highlight_context(Arc::new(highlight_context))
+///     .highlight_theme("Brand")
+///     .build()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct HighlightContextBuilder {
+    syntaxes: Vec<Source>,
+    themes: Vec<Source>,
+    aliases: HashMap<String, String>,
+}
+
+impl HighlightContextBuilder {
+    /// Add the `.sublime-syntax` definitions of a directory and its subdirectories.
+    pub fn syntaxes_from_folder(mut self, path: impl AsRef<Path>) -> Self {
+        self.syntaxes
+            .push(Source::Folder(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Add the `.sublime-syntax` definitions of a GResource directory, such as
+    /// `/com/example/App/syntaxes`.
+    pub fn syntaxes_from_resource(mut self, path: &str) -> Self {
+        self.syntaxes.push(Source::Resource(path.to_string()));
+        self
+    }
+
+    /// Add a syntax definition from the content of a `.sublime-syntax` file.
+    pub fn syntax_from_bytes(mut self, bytes: &[u8]) -> Self {
+        self.syntaxes.push(Source::Bytes(bytes.to_vec(), None));
+        self
+    }
+
+    /// Add the `.tmTheme` themes of a directory and its subdirectories, named after their file
+    /// name without extension.
+    pub fn themes_from_folder(mut self, path: impl AsRef<Path>) -> Self {
+        self.themes
+            .push(Source::Folder(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Add the `.tmTheme` themes of a GResource directory, named after their file name without
+    /// extension.
+    pub fn themes_from_resource(mut self, path: &str) -> Self {
+        self.themes.push(Source::Resource(path.to_string()));
+        self
+    }
+
+    /// Add a theme from the content of a `.tmTheme` file.
+    pub fn theme_from_bytes(mut self, name: &str, bytes: &[u8]) -> Self {
+        self.themes
+            .push(Source::Bytes(bytes.to_vec(), Some(name.to_string())));
+        self
+    }
+
+    /// Highlight the code fences of a language as another one, which is a syntax name or file
+    /// extension, such as `.alias("jsonc", "json")`. Aliases are case-insensitive.
+    ///
+    /// Aliases for `jsonc`, `json5`, `sh-session`, `console` and a few others are added by default.
+    pub fn alias(mut self, alias: &str, language: &str) -> Self {
+        self.aliases
+            .insert(alias.to_lowercase(), language.to_string());
+        self
+    }
+
+    /// Load the syntax definitions and themes.
+    ///
+    /// ## Errors
+    ///
+    /// This function will return an error if:
+    /// - a directory or GResource directory cannot be read
+    /// - a syntax definition or theme is invalid
+    /// - an alias refers to a language without syntax definition
+    pub fn build(self) -> anyhow::Result<HighlightContext> {
+        let syntax_set = if self.syntaxes.is_empty() {
+            SyntaxSet::load_defaults_newlines()
+        } else {
+            let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+            for source in self.syntaxes {
+                match source {
+                    Source::Folder(path) => builder
+                        .add_from_folder(&path, true)
+                        .with_context(|| format!("unable to load syntaxes from {path:?}"))?,
+                    Source::Resource(path) => {
+                        for (_, bytes) in resource_files(&path, ".sublime-syntax")? {
+                            builder.add(syntax_from_bytes(&bytes)?);
+                        }
+                    }
+                    Source::Bytes(bytes, _) => builder.add(syntax_from_bytes(&bytes)?),
+                }
+            }
+            builder.build()
+        };
+
+        let mut theme_set = ThemeSet::load_defaults();
+        for source in self.themes {
+            match source {
+                Source::Folder(path) => theme_set
+                    .add_from_folder(&path)
+                    .with_context(|| format!("unable to load themes from {path:?}"))?,
+                Source::Resource(path) => {
+                    for (name, bytes) in resource_files(&path, ".tmTheme")? {
+                        let theme = theme_from_bytes(&bytes)
+                            .with_context(|| format!("invalid theme {name}"))?;
+                        theme_set.themes.insert(name, theme);
+                    }
+                }
+                Source::Bytes(bytes, name) => {
+                    let name = name.unwrap_or_default();
+                    let theme = theme_from_bytes(&bytes)
+                        .with_context(|| format!("invalid theme {name}"))?;
+                    theme_set.themes.insert(name, theme);
+                }
+            }
+        }
+
+        let mut aliases = default_aliases();
+        for (alias, language) in self.aliases {
+            if syntax_set.find_syntax_by_token(&language).is_none() {
+                bail!("no syntax definition for language {language}, aliased as {alias}");
+            }
+            aliases.insert(alias, language);
+        }
+
+        Ok(HighlightContext {
+            syntax_set,
+            theme_set,
+            aliases,
+        })
+    }
+}
+
+/// CSS rules applying the colors set by a theme to the code blocks with the given class.
+fn theme_css(class: &str, settings: &ThemeSettings) -> String {
+    let selector = format!(".code_block_box.{class}");
+    let rules = [
+        (
+            selector.clone(),
+            vec![
+                ("background", settings.background),
+                ("color", settings.foreground),
+            ],
+        ),
+        (
+            format!("{selector} label > selection"),
+            vec![
+                ("background", settings.selection),
+                ("color", settings.selection_foreground),
+            ],
+        ),
+        (
+            format!("{selector} .code_block_gutter"),
+            vec![
+                ("background", settings.gutter),
+                ("color", settings.gutter_foreground),
+            ],
+        ),
+        (
+            format!("{selector} .code_block_highlighted_line"),
+            vec![("background", settings.line_highlight)],
+        ),
+    ];
+
+    let mut css = String::new();
+    for (selector, properties) in rules {
+        let declarations: Vec<String> = properties
+            .into_iter()
+            .filter_map(|(property, color)| Some(format!("{property}: {};", css_color(color?))))
+            .collect();
+        if !declarations.is_empty() {
+            css.push_str(&format!("{selector} {{ {} }}\n", declarations.join(" ")));
+        }
+    }
+    css
+}
+
+fn css_color(color: Color) -> String {
+    format!(
+        "rgba({}, {}, {}, {:.3})",
+        color.r,
+        color.g,
+        color.b,
+        f64::from(color.a) / 255.0
+    )
+}
+
+fn default_aliases() -> HashMap<String, String> {
+    DEFAULT_ALIASES
+        .iter()
+        .map(|(alias, language)| (alias.to_string(), language.to_string()))
+        .collect()
+}
+
+fn syntax_from_bytes(bytes: &[u8]) -> anyhow::Result<SyntaxDefinition> {
+    let content = std::str::from_utf8(bytes).context("syntax definition is not UTF-8")?;
+    SyntaxDefinition::load_from_str(content, true, None).context("invalid syntax definition")
+}
+
+fn theme_from_bytes(bytes: &[u8]) -> anyhow::Result<Theme> {
+    Ok(ThemeSet::load_from_reader(&mut Cursor::new(bytes))?)
+}
+
+/// Files of a GResource directory with the given extension, by name without extension.
+fn resource_files(path: &str, extension: &str) -> anyhow::Result<Vec<(String, glib::Bytes)>> {
+    let flags = gio::ResourceLookupFlags::NONE;
+    let path = path.trim_end_matches('/');
+    let mut files = Vec::new();
+    let children = gio::resources_enumerate_children(path, flags)
+        .with_context(|| format!("unable to list resources in {path}"))?;
+    for child in children {
+        let Some(name) = child.strip_suffix(extension) else {
+            continue;
+        };
+        let bytes = gio::resources_lookup_data(&format!("{path}/{child}"), flags)
+            .with_context(|| format!("unable to read resource {path}/{child}"))?;
+        files.push((name.to_string(), bytes));
+    }
+    Ok(files)
+}
@@ -0,0 +1,748 @@
+//! Rendering of raw HTML nodes, as Pango markup for inline HTML and as native widgets for block
+//! HTML.
+
+use std::collections::HashSet;
+
+use gtk::{pango, prelude::*};
+use html2pango::html_escape;
+use markdown::mdast::Node;
+
+use crate::{
+    append_widgets_from_children, create_picture, links, parse_code_block, urls::resolve,
+    DocumentCtx, ListContext,
+};
+
+/// Inline HTML tags that can be converted to Pango markup.
+pub const SUPPORTED_INLINE_HTML_TAGS: [&str; 19] = [
+    "b", "strong", "i", "em", "u", "ins", "s", "del", "strike", "sub", "sup", "small", "big",
+    "code", "tt", "kbd", "mark", "span", "br",
+];
+
+/// A parsed HTML tag.
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlTag {
+    /// Lowercase tag name
+    pub(crate) name: String,
+    pub(crate) closing: bool,
+    pub(crate) attributes: Vec<(String, String)>,
+}
+
+impl HtmlTag {
+    /// Value of an attribute, matched case-insensitively.
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse a single HTML tag such as `<span style="color: red">`, `</b>` or `<br/>`.
+///
+/// Returns `None` if the value is not exactly one tag.
+pub(crate) fn parse_tag(value: &str) -> Option<HtmlTag> {
+    let inner = value.trim().strip_prefix('<')?.strip_suffix('>')?;
+    let (closing, inner) = match inner.strip_prefix('/') {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+    let inner = inner.strip_suffix('/').unwrap_or(inner);
+    let name_end = inner
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(inner.len());
+    let name = &inner[..name_end];
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let mut attributes = Vec::new();
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let attribute_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let attribute = rest[..attribute_end].to_ascii_lowercase();
+        rest = rest[attribute_end..].trim_start();
+        let value = if let Some(after_equal) = rest.strip_prefix('=') {
+            let after_equal = after_equal.trim_start();
+            let (value, after_value) = match after_equal.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let quoted = &after_equal[1..];
+                    let value_end = quoted.find(quote)?;
+                    (&quoted[..value_end], &quoted[value_end + 1..])
+                }
+                _ => {
+                    let value_end = after_equal
+                        .find(char::is_whitespace)
+                        .unwrap_or(after_equal.len());
+                    (&after_equal[..value_end], &after_equal[value_end..])
+                }
+            };
+            rest = after_value.trim_start();
+            value.to_string()
+        } else {
+            String::new()
+        };
+        if attribute.is_empty() {
+            return None;
+        }
+        attributes.push((attribute, decode_entities(&value)));
+    }
+
+    Some(HtmlTag {
+        name: name.to_ascii_lowercase(),
+        closing,
+        attributes,
+    })
+}
+
+/// Decode the character references of HTML text or attribute values, such as `&amp;`, `&copy;`
+/// or `&#x2014;`. Unknown references are kept as they are.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        // References are short, don't look for their end in the whole text
+        let reference = rest
+            .char_indices()
+            .take(32)
+            .find(|&(_, c)| c == ';')
+            .and_then(|(end, _)| Some((end, decode_reference(&rest[..end])?)));
+        match reference {
+            Some((end, c)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => decoded.push('&'),
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_reference(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        // NUL and invalid code points are replaced, as HTML does
+        return Some(
+            char::from_u32(code)
+                .filter(|&c| c != '\0')
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        );
+    }
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "shy" => '\u{ad}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "middot" => '·',
+        "bull" => '•',
+        "deg" => '°',
+        "plusmn" => '±',
+        "times" => '×',
+        "divide" => '÷',
+        "euro" => '€',
+        "pound" => '£',
+        "yen" => '¥',
+        "cent" => '¢',
+        "sect" => '§',
+        "para" => '¶',
+        "larr" => '←',
+        "rarr" => '→',
+        "uarr" => '↑',
+        "darr" => '↓',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Convert an inline HTML node to Pango markup.
+///
+/// Tags of the allowlist are converted, comments are hidden and anything else is escaped as text.
+/// Closing markup of the opened tags is pushed to `open_tags`, so that unbalanced tags can be
+/// closed by the caller and stray closing tags are dropped.
+pub(crate) fn inline_markup(
+    value: &str,
+    allowlist: &HashSet<String>,
+    open_tags: &mut Vec<(String, &'static str)>,
+) -> String {
+    if value.starts_with("<!--") {
+        return String::new();
+    }
+    parse_tag(value)
+        .filter(|tag| allowlist.contains(&tag.name))
+        .and_then(|tag| tag_markup(tag, open_tags))
+        .unwrap_or_else(|| html_escape(value))
+}
+
+/// Convert a supported inline tag to Pango markup, or return `None` if it is not supported.
+fn tag_markup(tag: HtmlTag, open_tags: &mut Vec<(String, &'static str)>) -> Option<String> {
+    if tag.name == "br" {
+        return Some("\n".to_string());
+    }
+    if tag.closing {
+        return Some(
+            match open_tags.iter().rposition(|(name, _)| *name == tag.name) {
+                // Close the tags opened after this one too, to keep the markup well-formed
+                Some(position) => open_tags
+                    .drain(position..)
+                    .rev()
+                    .map(|(_, closing)| closing)
+                    .collect(),
+                None => String::new(),
+            },
+        );
+    }
+
+    let (opening, closing) = match tag.name.as_str() {
+        "b" | "strong" => ("<b>".to_string(), "</b>"),
+        "i" | "em" => ("<i>".to_string(), "</i>"),
+        "u" | "ins" => ("<u>".to_string(), "</u>"),
+        "s" | "del" | "strike" => ("<s>".to_string(), "</s>"),
+        "sub" => ("<sub>".to_string(), "</sub>"),
+        "sup" => ("<sup>".to_string(), "</sup>"),
+        "small" => ("<small>".to_string(), "</small>"),
+        "big" => ("<big>".to_string(), "</big>"),
+        "code" | "tt" => ("<tt>".to_string(), "</tt>"),
+        "kbd" => (
+            "<span font_family=\"monospace\" background=\"#808080\" bgalpha=\"30%\"> ".to_string(),
+            " </span>",
+        ),
+        "mark" => (
+            "<span background=\"#f6d32d\" foreground=\"#000000\">".to_string(),
+            "</span>",
+        ),
+        "span" => (span_opening(&tag), "</span>"),
+        _ => return None,
+    };
+    open_tags.push((tag.name, closing));
+    Some(opening)
+}
+
+/// Opening Pango markup of a `span` tag, keeping the colors of its `style` attribute.
+fn span_opening(tag: &HtmlTag) -> String {
+    let mut attributes = String::new();
+    for declaration in tag.attribute("style").unwrap_or_default().split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        // Only keep colors that Pango understands, which are also safe to embed in markup
+        if pango::Color::parse(value).is_err() {
+            continue;
+        }
+        match property.trim().to_ascii_lowercase().as_str() {
+            "color" => attributes.push_str(&format!(" foreground=\"{value}\"")),
+            "background" | "background-color" => {
+                attributes.push_str(&format!(" background=\"{value}\""))
+            }
+            _ => {}
+        }
+    }
+    format!("<span{attributes}>")
+}
+
+/// A token of raw HTML.
+#[derive(Debug)]
+pub(crate) enum HtmlToken<'a> {
+    Tag(HtmlTag),
+    Text(&'a str),
+    Comment,
+}
+
+/// Split raw HTML into tags, text and comments. Malformed tags are kept as text.
+pub(crate) fn tokenize(value: &str) -> Vec<HtmlToken> {
+    let mut tokens = Vec::new();
+    let mut rest = value;
+    while !rest.is_empty() {
+        let Some(tag_start) = rest.find('<') else {
+            tokens.push(HtmlToken::Text(rest));
+            break;
+        };
+        if tag_start > 0 {
+            tokens.push(HtmlToken::Text(&rest[..tag_start]));
+            rest = &rest[tag_start..];
+        }
+
+        if rest.starts_with("<!--") {
+            let comment_end = rest.find("-->").map_or(rest.len(), |end| end + 3);
+            tokens.push(HtmlToken::Comment);
+            rest = &rest[comment_end..];
+            continue;
+        }
+
+        // Find the end of the tag, ignoring `>` in quoted attribute values
+        let mut quote = None;
+        let tag_end = rest.char_indices().find_map(|(i, c)| match (quote, c) {
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                None
+            }
+            (Some(q), c) if q == c => {
+                quote = None;
+                None
+            }
+            (None, '>') => Some(i + 1),
+            _ => None,
+        });
+        match tag_end.and_then(|end| parse_tag(&rest[..end]).map(|tag| (tag, end))) {
+            Some((tag, end)) => {
+                tokens.push(HtmlToken::Tag(tag));
+                rest = &rest[end..];
+            }
+            None => {
+                tokens.push(HtmlToken::Text("<"));
+                rest = &rest[1..];
+            }
+        }
+    }
+    tokens
+}
+
+/// Tags that never have a closing tag.
+fn is_void(name: &str) -> bool {
+    matches!(name, "img" | "br" | "hr" | "source" | "input" | "wbr")
+}
+
+/// Horizontal alignment requested by a block tag, either with `<center>` or an `align` attribute.
+fn block_alignment(tag: &HtmlTag) -> Option<gtk::Align> {
+    if tag.name == "center" {
+        return Some(gtk::Align::Center);
+    }
+    if !matches!(
+        tag.name.as_str(),
+        "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+    ) {
+        return None;
+    }
+    match tag.attribute("align")?.to_ascii_lowercase().as_str() {
+        "center" => Some(gtk::Align::Center),
+        "right" => Some(gtk::Align::End),
+        "left" => Some(gtk::Align::Start),
+        _ => None,
+    }
+}
+
+/// Whether a token is a tag that can start a block rendered with native widgets.
+fn is_supported_block(token: &HtmlToken) -> bool {
+    match token {
+        HtmlToken::Tag(tag) if !tag.closing => {
+            tag.name == "details" || tag.name == "img" || block_alignment(tag).is_some()
+        }
+        _ => false,
+    }
+}
+
+fn is_blank(token: &HtmlToken) -> bool {
+    match token {
+        HtmlToken::Text(text) => text.trim().is_empty(),
+        HtmlToken::Comment => true,
+        HtmlToken::Tag(_) => false,
+    }
+}
+
+/// Position of the tag closing the one opened just before `tokens`, if any.
+fn find_closing_tag(tokens: &[HtmlToken], name: &str) -> Option<usize> {
+    let mut depth = 1;
+    for (position, token) in tokens.iter().enumerate() {
+        if let HtmlToken::Tag(tag) = token {
+            if tag.name == name {
+                depth += if tag.closing { -1 } else { 1 };
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Index of the HTML sibling closing a tag left open by a previous sibling, if any.
+fn find_closing_sibling(siblings: &[Node], name: &str) -> Option<usize> {
+    let mut depth = 1;
+    for (index, sibling) in siblings.iter().enumerate() {
+        if let Node::Html(html) = sibling {
+            for token in tokenize(&html.value) {
+                if let HtmlToken::Tag(tag) = token {
+                    if tag.name == name {
+                        depth += if tag.closing { -1 } else { 1 };
+                        if depth == 0 {
+                            return Some(index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Index of the sibling following the node at `index` of `children`, after the siblings wrapped by
+/// a block HTML node, as [`append_block`] renders them.
+pub(crate) fn block_end(children: &[Node], index: usize) -> usize {
+    let Node::Html(html) = &children[index] else {
+        return index + 1;
+    };
+    let tokens = tokenize(&html.value);
+    if !tokens
+        .iter()
+        .find(|token| !is_blank(token))
+        .is_some_and(is_supported_block)
+    {
+        return index + 1;
+    }
+
+    let mut next_index = index + 1;
+    let mut position = 0;
+    while position < tokens.len() {
+        let token = &tokens[position];
+        position += 1;
+        match token {
+            HtmlToken::Tag(tag) if tag.name != "img" && is_supported_block(token) => {
+                match find_closing_tag(&tokens[position..], &tag.name) {
+                    Some(closing) => position += closing + 1,
+                    None => {
+                        position = tokens.len();
+                        if let Some(closing) =
+                            find_closing_sibling(&children[next_index..], &tag.name)
+                        {
+                            next_index += closing + 1;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    next_index
+}
+
+/// Update the block elements left open, such as `<details>`, with the tags of raw HTML, so that
+/// the siblings wrapped by a block HTML node are not split apart.
+pub(crate) fn track_open_blocks(value: &str, open: &mut Vec<String>) {
+    for token in tokenize(value) {
+        let HtmlToken::Tag(tag) = &token else {
+            continue;
+        };
+        if tag.closing {
+            if let Some(position) = open.iter().rposition(|name| *name == tag.name) {
+                open.truncate(position);
+            }
+        } else if tag.name != "img" && (is_supported_block(&token) || open.contains(&tag.name)) {
+            open.push(tag.name.clone());
+        }
+    }
+}
+
+/// Render the block HTML node at `index` of `children` and append it to the root `gtk::Box`.
+///
+/// `<details>`, aligned blocks and `<img>` tags are rendered as native widgets. Blocks opened
+/// without being closed wrap the following siblings, up to the one closing them. Other block HTML
+/// is displayed as a dimmed code block, so that no content is lost.
+///
+/// Returns the index of the next sibling to render.
+pub(crate) fn append_block(
+    children: &[Node],
+    index: usize,
+    root: &gtk::Box,
+    doc_ctx: &DocumentCtx,
+    list_ctx: &mut ListContext,
+) -> usize {
+    let Node::Html(html) = &children[index] else {
+        return index + 1;
+    };
+    let tokens = tokenize(&html.value);
+    let Some(first) = tokens.iter().find(|token| !is_blank(token)) else {
+        // Only comments
+        return index + 1;
+    };
+    if !is_supported_block(first) {
+        let code_block = parse_code_block(None, None, html.value.trim_end(), root, doc_ctx);
+        code_block.set_opacity(0.6);
+        code_block.add_css_class("commonmark_raw_html");
+        return index + 1;
+    }
+
+    let no_siblings: &[Node] = &[];
+    let mut next_index = index + 1;
+    let mut position = 0;
+    while position < tokens.len() {
+        let token = &tokens[position];
+        position += 1;
+        match token {
+            HtmlToken::Tag(tag) if tag.name == "img" => {
+                if let Some(picture) = html_picture(tag, gtk::Align::Start, doc_ctx) {
+                    root.append(&picture);
+                }
+            }
+            HtmlToken::Tag(tag) if is_supported_block(token) => {
+                let (inner, siblings) = match find_closing_tag(&tokens[position..], &tag.name) {
+                    Some(closing) => {
+                        let inner = &tokens[position..position + closing];
+                        position += closing + 1;
+                        (inner, no_siblings)
+                    }
+                    None => {
+                        let inner = &tokens[position..];
+                        position = tokens.len();
+                        match find_closing_sibling(&children[next_index..], &tag.name) {
+                            Some(closing) => {
+                                let siblings = &children[next_index..next_index + closing];
+                                next_index += closing + 1;
+                                (inner, siblings)
+                            }
+                            None => (inner, no_siblings),
+                        }
+                    }
+                };
+                root.append(&container_widget(tag, inner, siblings, doc_ctx, list_ctx));
+            }
+            _ if is_blank(token) => {}
+            _ => {
+                // Stray content between blocks
+                let end = tokens[position..]
+                    .iter()
+                    .position(is_supported_block)
+                    .map_or(tokens.len(), |end| position + end);
+                append_content(&tokens[position - 1..end], root, None, doc_ctx);
+                position = end;
+            }
+        }
+    }
+    next_index
+}
+
+/// Create the widget of a `<details>` or an aligned block, containing its inner HTML followed by
+/// the sibling nodes it wraps.
+fn container_widget(
+    tag: &HtmlTag,
+    inner: &[HtmlToken],
+    siblings: &[Node],
+    doc_ctx: &DocumentCtx,
+    list_ctx: &mut ListContext,
+) -> gtk::Widget {
+    let align = block_alignment(tag);
+    let content_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(10)
+        .halign(align.unwrap_or(gtk::Align::Fill))
+        .build();
+
+    let mut inner = inner;
+    let mut summary = None;
+    if tag.name == "details" {
+        // The summary is the label of the expander
+        let summary_start = inner.iter().position(|token| !is_blank(token));
+        if let Some(HtmlToken::Tag(summary_tag)) = summary_start.map(|start| &inner[start]) {
+            if summary_tag.name == "summary" && !summary_tag.closing {
+                let start = summary_start.unwrap_or_default() + 1;
+                let end = find_closing_tag(&inner[start..], "summary")
+                    .map_or(inner.len(), |end| start + end);
+                summary = Some(content_markup(&inner[start..end], doc_ctx));
+                inner = &inner[(end + 1).min(inner.len())..];
+            }
+        }
+    }
+
+    let heading_size = match tag.name.as_str() {
+        "h1" => Some("xx-large"),
+        "h2" => Some("x-large"),
+        "h3" => Some("large"),
+        _ => None,
+    };
+    append_content(inner, &content_box, heading_size, doc_ctx);
+    if !siblings.is_empty() {
+        append_widgets_from_children(siblings, &content_box, None, doc_ctx, list_ctx, None);
+    }
+
+    if tag.name == "details" {
+        gtk::Expander::builder()
+            .label(summary.unwrap_or_else(|| "Details".to_string()))
+            .use_markup(true)
+            .expanded(tag.attribute("open").is_some())
+            .child(&content_box)
+            .name("commonmark_details_expander")
+            .build()
+            .upcast()
+    } else {
+        content_box.set_widget_name("commonmark_aligned_box");
+        content_box.upcast()
+    }
+}
+
+/// Append the content of an HTML block to a `gtk::Box`: text and inline tags as labels, and
+/// images as pictures. Other tags are dropped, but their text is kept.
+fn append_content(
+    tokens: &[HtmlToken],
+    root: &gtk::Box,
+    font_size: Option<&str>,
+    doc_ctx: &DocumentCtx,
+) {
+    let align = root.halign();
+    let mut start = 0;
+    for (position, token) in tokens.iter().enumerate() {
+        if let HtmlToken::Tag(tag) = token {
+            if tag.name == "img" && !tag.closing {
+                append_label(&tokens[start..position], root, font_size, doc_ctx);
+                if let Some(picture) = html_picture(tag, align, doc_ctx) {
+                    root.append(&picture);
+                }
+                start = position + 1;
+            }
+        }
+    }
+    append_label(&tokens[start..], root, font_size, doc_ctx);
+}
+
+fn append_label(
+    tokens: &[HtmlToken],
+    root: &gtk::Box,
+    font_size: Option<&str>,
+    doc_ctx: &DocumentCtx,
+) {
+    let markup = content_markup(tokens, doc_ctx);
+    if markup.trim().is_empty() {
+        return;
+    }
+    let markup = match font_size {
+        Some(size) => format!("<span font_size=\"{size}\">{markup}</span>"),
+        None => markup,
+    };
+    let align = root.halign();
+    let label = gtk::Label::builder()
+        .label(markup)
+        .use_markup(true)
+        .wrap(true)
+        .halign(align)
+        .justify(match align {
+            gtk::Align::Center => gtk::Justification::Center,
+            gtk::Align::End => gtk::Justification::Right,
+            _ => gtk::Justification::Left,
+        })
+        .build();
+    links::connect_label(&label, doc_ctx);
+    root.append(&label);
+}
+
+/// Convert HTML content to Pango markup: inline tags of the allowlist and links are kept, other
+/// tags are dropped and text is escaped.
+fn content_markup(tokens: &[HtmlToken], doc_ctx: &DocumentCtx) -> String {
+    let allowlist = &doc_ctx.render_config.inline_html_tags;
+    let mut open_tags = Vec::new();
+    let mut markup = String::new();
+    for token in tokens {
+        match token {
+            // Collapse whitespace as HTML does, before decoding so that `&nbsp;` is kept
+            HtmlToken::Text(text) => markup.push_str(&html_escape(&decode_entities(
+                &text.split_ascii_whitespace().collect::<Vec<_>>().join(" "),
+            ))),
+            HtmlToken::Tag(tag) if tag.name == "a" => match (
+                tag.closing,
+                tag.attribute("href")
+                    .and_then(|href| resolve(href, doc_ctx.render_config)),
+            ) {
+                (false, Some(href)) => {
+                    markup.push_str(&format!("<a href=\"{}\">", html_escape(&href)));
+                    open_tags.push(("a".to_string(), "</a>"));
+                }
+                (true, _) => {
+                    if let Some(position) = open_tags.iter().rposition(|(name, _)| name == "a") {
+                        markup.extend(open_tags.drain(position..).rev().map(|(_, c)| c));
+                    }
+                }
+                _ => {}
+            },
+            HtmlToken::Tag(tag) if allowlist.contains(&tag.name) => {
+                let raw = format!("<{}{}>", if tag.closing { "/" } else { "" }, tag.name);
+                // Attributes are only used by spans, for their colors
+                if tag.name == "span" && !tag.closing {
+                    markup.push_str(&span_opening(tag));
+                    open_tags.push(("span".to_string(), "</span>"));
+                } else {
+                    markup.push_str(&inline_markup(&raw, allowlist, &mut open_tags));
+                }
+            }
+            HtmlToken::Tag(tag) if matches!(tag.name.as_str(), "p" | "div") && tag.closing => {
+                markup.push('\n');
+            }
+            HtmlToken::Tag(_) | HtmlToken::Comment => {}
+        }
+    }
+    markup.extend(open_tags.into_iter().rev().map(|(_, closing)| closing));
+    markup.trim().to_string()
+}
+
+/// Create the picture of an `<img>` tag, with the size given by its `width` and `height`
+/// attributes.
+fn html_picture(tag: &HtmlTag, align: gtk::Align, doc_ctx: &DocumentCtx) -> Option<gtk::Picture> {
+    let src = tag.attribute("src")?;
+    // Percentages cannot be honored, as the available width is unknown
+    let dimension = |name| {
+        tag.attribute(name)
+            .and_then(|value| value.trim_end_matches("px").parse::<i32>().ok())
+    };
+    let size = match (dimension("width"), dimension("height")) {
+        (None, None) => None,
+        (width, height) => Some((width.unwrap_or(-1), height.unwrap_or(-1))),
+    };
+    let picture = create_picture(src, size, doc_ctx)?;
+    picture.set_halign(align);
+    picture.set_tooltip_text(tag.attribute("title").or_else(|| tag.attribute("alt")));
+    Some(picture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_references() {
+        assert_eq!(decode_entities("a&nbsp;b"), "a\u{a0}b");
+        assert_eq!(
+            decode_entities("&copy; 2024 &amp; &lt;b&gt;"),
+            "© 2024 & <b>"
+        );
+    }
+
+    #[test]
+    fn numeric_references() {
+        assert_eq!(decode_entities("&#169;&#xA9;&#XA9;"), "©©©");
+        assert_eq!(decode_entities("&#0;&#x110000;"), "\u{fffd}\u{fffd}");
+        assert_eq!(decode_entities("&#;&#x;&#xZ;"), "&#;&#x;&#xZ;");
+    }
+
+    #[test]
+    fn unknown_references_are_kept() {
+        assert_eq!(decode_entities("AT&T"), "AT&T");
+        assert_eq!(decode_entities("&unknown; &amp"), "&unknown; &amp");
+        assert_eq!(decode_entities("&&amp;&"), "&&&");
+        assert_eq!(decode_entities("é&eacute"), "é&eacute");
+    }
+
+    #[test]
+    fn attribute_values_are_decoded() {
+        let tag = parse_tag(r#"<img src="image.png?a=1&amp;b=2" alt='&quot;x&quot;'>"#).unwrap();
+        assert_eq!(tag.attribute("src"), Some("image.png?a=1&b=2"));
+        assert_eq!(tag.attribute("alt"), Some("\"x\""));
+    }
+}
@@ -0,0 +1,381 @@
+//! Asynchronous image loaders, and pictures showing a placeholder until their image is loaded.
+
+use std::{fmt::Debug, future::Future, pin::Pin, rc::Rc};
+
+use anyhow::{bail, Context};
+use gtk::{gdk, gio, glib, prelude::*};
+
+use crate::urls::scheme;
+
+/// Future resolving to a loaded image.
+pub type ImageFuture = Pin<Box<dyn Future<Output = anyhow::Result<gdk::Paintable>>>>;
+
+/// Load images referenced by the markdown input, for use with [`ImageSetting::Loader`].
+///
+/// Loaders are called from the main thread, and their futures are polled on the default main
+/// context.
+///
+/// [`ImageSetting::Loader`]: crate::ImageSetting::Loader
+pub trait ImageLoader: Debug {
+    /// Whether this loader handles the URL.
+    fn handles(&self, url: &str) -> bool;
+
+    /// Load the image at the URL.
+    fn load(&self, url: &str) -> ImageFuture;
+}
+
+/// Loader trying each of its loaders in turn, using the first one that handles the URL.
+///
+/// The default loaders handle data URIs, `resource://` URIs, `http://` and `https://` URLs if the
+/// `http` feature is enabled, and local paths or `file://` URIs.
+#[derive(Debug, Clone)]
+pub struct ImageLoaders(Vec<Rc<dyn ImageLoader>>);
+
+impl ImageLoaders {
+    /// Loaders without any loader, that do not handle any URL.
+    pub fn empty() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Add a loader, tried after the current ones.
+    pub fn with(mut self, loader: impl ImageLoader + 'static) -> Self {
+        self.0.push(Rc::new(loader));
+        self
+    }
+}
+
+impl Default for ImageLoaders {
+    fn default() -> Self {
+        let loaders = Self::empty()
+            .with(DataUriImageLoader)
+            .with(ResourceImageLoader);
+        #[cfg(feature = "http")]
+        let loaders = loaders.with(HttpImageLoader::default());
+        loaders.with(FileImageLoader)
+    }
+}
+
+impl ImageLoader for ImageLoaders {
+    fn handles(&self, url: &str) -> bool {
+        self.0.iter().any(|loader| loader.handles(url))
+    }
+
+    fn load(&self, url: &str) -> ImageFuture {
+        match self.0.iter().find(|loader| loader.handles(url)) {
+            Some(loader) => loader.load(url),
+            None => {
+                let url = url.to_string();
+                Box::pin(async move { bail!("no image loader handles {url}") })
+            }
+        }
+    }
+}
+
+/// Load images from local paths, relative to the working directory, and `file://` URIs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileImageLoader;
+
+impl ImageLoader for FileImageLoader {
+    fn handles(&self, url: &str) -> bool {
+        matches!(scheme(url).as_deref(), None | Some("file"))
+    }
+
+    fn load(&self, url: &str) -> ImageFuture {
+        let file = match scheme(url) {
+            Some(_) => gio::File::for_uri(url),
+            None => gio::File::for_path(url),
+        };
+        Box::pin(async move {
+            let (bytes, _) = file.load_bytes_future().await?;
+            texture_from_bytes(&bytes)
+        })
+    }
+}
+
+/// Load images from the registered GResources, with `resource://` URIs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceImageLoader;
+
+impl ImageLoader for ResourceImageLoader {
+    fn handles(&self, url: &str) -> bool {
+        scheme(url).as_deref() == Some("resource")
+    }
+
+    fn load(&self, url: &str) -> ImageFuture {
+        let path = url
+            .strip_prefix("resource://")
+            .map(str::to_string)
+            .with_context(|| format!("malformed resource URI {url}"));
+        Box::pin(async move {
+            let bytes = gio::resources_lookup_data(&path?, gio::ResourceLookupFlags::NONE)?;
+            texture_from_bytes(&bytes)
+        })
+    }
+}
+
+/// Load images embedded in `data:` URIs, either base64 or percent-encoded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DataUriImageLoader;
+
+impl DataUriImageLoader {
+    /// Decode the content of a data URI.
+    pub fn decode(url: &str) -> anyhow::Result<Vec<u8>> {
+        let Some((header, data)) = url.split_once(':').and_then(|(_, uri)| uri.split_once(','))
+        else {
+            bail!("malformed data URI");
+        };
+        let data = glib::uri_unescape_string(data, None::<&str>)
+            .context("malformed percent-encoding in data URI")?;
+        if header.ends_with(";base64") {
+            let data: String = data.split_whitespace().collect();
+            Ok(glib::base64_decode(&data))
+        } else {
+            Ok(data.as_bytes().to_vec())
+        }
+    }
+}
+
+impl ImageLoader for DataUriImageLoader {
+    fn handles(&self, url: &str) -> bool {
+        scheme(url).as_deref() == Some("data")
+    }
+
+    fn load(&self, url: &str) -> ImageFuture {
+        let data = Self::decode(url);
+        Box::pin(async move { texture_from_bytes(&glib::Bytes::from_owned(data?)) })
+    }
+}
+
+/// Load images from `http://` and `https://` URLs, on a few threads shared by all loaders.
+///
+/// Requires the `http` feature.
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub struct HttpImageLoader {
+    agent: ureq::Agent,
+    max_size: u64,
+}
+
+/// Threads downloading images, shared by all HTTP loaders so that a document with many images
+/// does not start a thread for each of them.
+#[cfg(feature = "http")]
+static DOWNLOAD_THREADS: glib::once_cell::sync::Lazy<glib::ThreadPool> =
+    glib::once_cell::sync::Lazy::new(|| {
+        // Only exclusive thread pools can fail to be created
+        glib::ThreadPool::shared(Some(4)).expect("unable to create the download thread pool")
+    });
+
+#[cfg(feature = "http")]
+impl Default for HttpImageLoader {
+    /// Loader timing out after 10 seconds when connecting, or 30 seconds without receiving data.
+    fn default() -> Self {
+        Self::new(
+            ureq::AgentBuilder::new()
+                .timeout_connect(std::time::Duration::from_secs(10))
+                .timeout_read(std::time::Duration::from_secs(30))
+                .build(),
+        )
+    }
+}
+
+#[cfg(feature = "http")]
+impl HttpImageLoader {
+    /// Loader sending its requests with the given agent, which sets timeouts, proxies and TLS.
+    pub fn new(agent: ureq::Agent) -> Self {
+        Self {
+            agent,
+            max_size: 20 * 1024 * 1024,
+        }
+    }
+
+    /// Set the maximum size of a downloaded image in bytes, 20 MiB by default.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Download the content at the URL, blocking the current thread.
+    pub fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        use std::io::Read;
+
+        let response = self.agent.get(url).call()?;
+        let mut bytes = Vec::new();
+        response
+            .into_reader()
+            .take(self.max_size + 1)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() as u64 > self.max_size {
+            bail!("image is larger than {} bytes", self.max_size);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(feature = "http")]
+impl ImageLoader for HttpImageLoader {
+    fn handles(&self, url: &str) -> bool {
+        matches!(scheme(url).as_deref(), Some("http" | "https"))
+    }
+
+    fn load(&self, url: &str) -> ImageFuture {
+        let (sender, receiver) = futures_channel::oneshot::channel();
+        let loader = self.clone();
+        let url = url.to_string();
+        let queued = DOWNLOAD_THREADS.push(move || {
+            let _ = sender.send(loader.fetch(&url));
+        });
+        Box::pin(async move {
+            queued.context("unable to queue image download")?;
+            let bytes = receiver.await.context("image download was interrupted")??;
+            texture_from_bytes(&glib::Bytes::from_owned(bytes))
+        })
+    }
+}
+
+fn texture_from_bytes(bytes: &glib::Bytes) -> anyhow::Result<gdk::Paintable> {
+    Ok(gdk::Texture::from_bytes(bytes)?.upcast())
+}
+
+/// Paintable of a themed icon, used as image placeholder.
+fn icon_paintable(name: &str) -> Option<gdk::Paintable> {
+    let display = gdk::Display::default()?;
+    let icon = gtk::IconTheme::for_display(&display).lookup_icon(
+        name,
+        &[],
+        48,
+        1,
+        gtk::TextDirection::None,
+        gtk::IconLookupFlags::empty(),
+    );
+    Some(icon.upcast())
+}
+
+/// Create a picture showing a placeholder, then the image once it is loaded.
+///
+/// ## Logging
+///
+/// A warning log is emitted if the image cannot be loaded, in which case the placeholder is
+/// replaced by a missing image icon.
+pub(crate) fn loading_picture(url: &str, loader: &dyn ImageLoader) -> gtk::Picture {
+    let picture = gtk::Picture::builder()
+        .css_classes(vec!["image_placeholder"])
+        .build();
+    picture.set_paintable(icon_paintable("image-loading-symbolic").as_ref());
+
+    let future = loader.load(url);
+    let url = url.to_string();
+    let loading_picture = picture.downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let result = future.await;
+        let Some(picture) = loading_picture.upgrade() else {
+            return;
+        };
+        match result {
+            Ok(paintable) => {
+                picture.remove_css_class("image_placeholder");
+                picture.set_paintable(Some(&paintable));
+            }
+            Err(err) => {
+                log::warn!("unable to load image {url}: {err}");
+                picture.set_paintable(icon_paintable("image-missing-symbolic").as_ref());
+            }
+        }
+    });
+
+    picture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_resource_uris_are_rejected() {
+        let context = glib::MainContext::new();
+        for url in ["resource:aé", "resource:/image.png"] {
+            let err = context.block_on(ResourceImageLoader.load(url)).unwrap_err();
+            assert_eq!(err.to_string(), format!("malformed resource URI {url}"));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "http"))]
+mod http_tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// A transparent 1×1 PNG image.
+    const PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f,
+        0x15, 0xc4, 0x89, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0x00, 0x02, 0x00, 0x00, 0x05, 0x00, 0x01, 0x7a, 0x5e, 0xab, 0x3f, 0x00, 0x00, 0x00, 0x00,
+        0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    /// Serve the PNG image to the given number of requests, and return its URL.
+    fn serve_png(requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                // Skip the request head, up to the empty line
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n",
+                    PNG.len()
+                )
+                .unwrap();
+                stream.write_all(PNG).unwrap();
+            }
+        });
+        format!("http://{address}/image.png")
+    }
+
+    fn agent() -> ureq::Agent {
+        ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(5))
+            .build()
+    }
+
+    #[test]
+    fn http_loader_fetches_images() {
+        let url = serve_png(1);
+        let loader = HttpImageLoader::new(agent());
+        assert!(loader.handles(&url));
+        assert_eq!(loader.fetch(&url).unwrap(), PNG);
+    }
+
+    #[test]
+    fn http_loader_rejects_images_larger_than_max_size() {
+        let url = serve_png(2);
+        let size = PNG.len() as u64;
+        assert_eq!(
+            HttpImageLoader::new(agent())
+                .max_size(size)
+                .fetch(&url)
+                .unwrap(),
+            PNG
+        );
+        let err = HttpImageLoader::new(agent())
+            .max_size(size - 1)
+            .fetch(&url)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("image is larger than {} bytes", size - 1)
+        );
+    }
+}
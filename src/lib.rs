@@ -21,57 +21,280 @@
 //! // root.container_add(&clamp);
 //! ```
 
+mod anchors;
+mod blocks;
+mod code_meta;
+mod code_overlay;
+mod color_scheme;
+mod config;
+mod footnotes;
+mod front_matter;
+mod highlight;
+mod html;
+mod images;
+mod links;
+mod math;
+mod outline;
+mod streaming;
+mod urls;
+mod view;
+
+use std::{cell::RefCell, collections::HashMap};
+
+use anchors::{Anchors, Slugger};
 use anyhow::Context;
-use gtk::{
-    gdk::Display,
-    traits::{BoxExt, GridExt, WidgetExt},
-    CssProvider, StyleContext,
+use blocks::Blocks;
+use code_meta::CodeMeta;
+use code_overlay::{code_overlay, language_badge};
+use color_scheme::CodeBlocks;
+pub use config::{
+    available_highlight_themes, CodeBlockColors, ImageSetting, ListNumbering, RenderConfig,
+    RenderConfigBuilder,
 };
+use footnotes::Footnotes;
+pub use front_matter::{FrontMatter, FrontMatterFormat, FrontMatterSetting, FrontMatterValue};
+use gtk::{gdk, gdk::Display, gdk_pixbuf, gio, glib, prelude::*, CssProvider, StyleContext};
+pub use highlight::{HighlightContext, HighlightContextBuilder};
+pub use html::SUPPORTED_INLINE_HTML_TAGS;
 use html2pango::html_escape;
+#[cfg(feature = "http")]
+pub use images::HttpImageLoader;
+pub use images::{
+    DataUriImageLoader, FileImageLoader, ImageFuture, ImageLoader, ImageLoaders,
+    ResourceImageLoader,
+};
+pub use links::{LinkActivation, LinkPolicy};
 pub use markdown::ParseOptions;
-use markdown::{self, mdast::Node};
-use syntect::{
+use markdown::{
     self,
-    easy::HighlightLines,
-    highlighting::{Style, ThemeSet},
-    parsing::SyntaxSet,
-    util::LinesWithEndings,
+    mdast::{AlignKind, Definition, Node, ReferenceKind},
 };
+use math::InlineFormulas;
+pub use outline::{Outline, OutlineItem};
+pub use streaming::StreamingRenderer;
+use syntect::{self, easy::HighlightLines, highlighting::Style, util::LinesWithEndings};
+pub use view::MarkdownView;
 
-#[derive(Debug, Clone)]
-pub enum ImageSetting {
-    /// Do not show images
-    Ignore,
-    /// Show images from their path on disk
-    FromPath,
-    /// Show images by embedding them at compilation time
-    IncludeBytes,
+/// Create widgets from commonmark input and return them in a new `gtk::Viewport`.
+///
+/// Use [`render_document`] to also get the document metadata.
+///
+/// ## Errors
+///
+/// The only errors that can occur are from the commonmark parser crate [markdown-rs](https://github.com/wooorm/markdown-rs),
+/// which states that only the MDX commonmark extension can have syntax errors.
+///
+/// ## Logging
+///
+/// Warning logs will be emitted if a code block language name or meta string is invalid.
+pub fn render_input(input: &str, render_config: RenderConfig) -> anyhow::Result<gtk::Viewport> {
+    render_document(input, &render_config).map(|document| document.viewport)
 }
 
-/// Render configuration options.
-///
-/// Default implementation uses the "base16-mocha.dark" theme for code highlighting
-/// and a parser for the Github flavored Markdown.
+/// Register the types of this crate, so that they can be used in `gtk::Builder` `.ui` files and
+/// Blueprint. GTK must be initialized first.
+pub fn init() {
+    MarkdownView::ensure_type();
+    OutlineItem::ensure_type();
+}
+
+/// A rendered document: its widgets, and the metadata collected while rendering it.
 #[derive(Debug)]
-pub struct RenderConfig<'a> {
-    image_settings: ImageSetting,
-    /// Configuration that describes how to parse markdown
-    parse_options: ParseOptions,
-    /// For available themes, please refer to the [syntect](https://github.com/trishume/syntect) documentation.
-    highlight_theme: &'a str,
+pub struct RenderedDocument {
+    viewport: gtk::Viewport,
+    content_box: gtk::Box,
+    front_matter: Option<FrontMatter>,
+    anchors: Anchors,
+    outline: Outline,
+    /// Widgets of the top-level blocks, reused when updating the document
+    blocks: Blocks,
+    /// Code blocks highlighted again when the color scheme changes
+    code_blocks: CodeBlocks,
 }
 
-impl Default for RenderConfig<'_> {
-    fn default() -> Self {
-        Self {
-            image_settings: ImageSetting::FromPath,
-            parse_options: ParseOptions::gfm(),
-            highlight_theme: "base16-mocha.dark",
+impl RenderedDocument {
+    /// The `gtk::Viewport` containing the document widgets.
+    pub fn viewport(&self) -> &gtk::Viewport {
+        &self.viewport
+    }
+
+    /// The YAML or TOML front matter of the document, if it has one.
+    ///
+    /// Front matter is only parsed if the `frontmatter` construct is enabled in the `ParseOptions`.
+    pub fn front_matter(&self) -> Option<&FrontMatter> {
+        self.front_matter.as_ref()
+    }
+
+    /// Scroll the surrounding `gtk::ScrolledWindow` to the heading with the given slug, with or
+    /// without its leading `#`.
+    ///
+    /// Heading slugs are GitHub-compatible: `## Getting started` has the `getting-started` slug,
+    /// and a second heading with the same text has the `getting-started-1` slug.
+    ///
+    /// Returns `false` if the anchor is unknown, or if the document is not inside a
+    /// `gtk::ScrolledWindow`. The document must be allocated, so this cannot be called right after
+    /// rendering it, but can be from an idle callback.
+    pub fn scroll_to_anchor(&self, anchor: &str) -> bool {
+        self.anchors
+            .scroll_to(anchor.strip_prefix('#').unwrap_or(anchor))
+    }
+
+    /// Outline of the document, made of its headings.
+    pub fn outline(&self) -> &Outline {
+        &self.outline
+    }
+
+    /// Create a sidebar listing the headings of the document.
+    ///
+    /// Clicking a heading scrolls the document to it, and the heading of the section in view is
+    /// selected as the document is scrolled. The viewport must be put inside a
+    /// `gtk::ScrolledWindow`.
+    pub fn outline_sidebar(&self) -> gtk::ScrolledWindow {
+        self.outline.sidebar(&self.viewport)
+    }
+
+    /// Render new input in place of the current one, only replacing the widgets of the top-level
+    /// blocks that changed.
+    ///
+    /// The widgets of unchanged blocks are kept along with their state, such as expanded
+    /// `<details>` blocks or selected text, and so is the scroll position. Every block is rendered
+    /// again if the front matter or the link definitions changed, or if the document has
+    /// footnotes.
+    ///
+    /// The render configuration must be the one the document was rendered with. The outline is
+    /// updated in place, so that the models and sidebars created from it follow the new headings.
+    ///
+    /// ## Errors
+    ///
+    /// The errors are those of [`render_document`], in which case the document is unchanged.
+    pub fn update(&mut self, input: &str, render_config: &RenderConfig) -> anyhow::Result<()> {
+        let highlight_context = render_config.highlight_context();
+        let theme_name = self.code_blocks.configure(render_config);
+        let syntect_ctx = SyntectCtx {
+            highlight_context: &highlight_context,
+            theme_name: &theme_name,
+            code_blocks: &self.code_blocks,
+        };
+        load_css();
+
+        // Read commonmark
+        let tree = markdown::to_mdast(input, &render_config.parse_options)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| "commonmark parsing error")?;
+        let children = tree.children().map_or(&[][..], Vec::as_slice);
+        let mut definitions = HashMap::new();
+        collect_definitions(children, &mut definitions);
+        self.anchors.clear();
+        let doc_ctx = DocumentCtx {
+            syntect_ctx,
+            render_config,
+            definitions,
+            anchors: self.anchors.clone(),
+            slugger: RefCell::default(),
+            outline: RefCell::default(),
+            footnotes: RefCell::new(Footnotes::collect(children)),
+            inline_formulas: RefCell::default(),
+        };
+        self.front_matter = FrontMatter::from_nodes(children);
+        self.blocks.update(
+            input,
+            children,
+            &self.content_box,
+            self.front_matter.as_ref(),
+            &doc_ctx,
+        );
+        self.outline.replace(doc_ctx.outline.take());
+
+        Ok(())
+    }
+
+    /// Render a part of a document received in chunks after the blocks rendered so far,
+    /// continuing the slugs and headings of the previous parts.
+    ///
+    /// The outline of the document is not updated, see [`RenderedDocument::set_outline`].
+    ///
+    /// ## Errors
+    ///
+    /// The errors are those of [`render_document`], in which case the document is unchanged.
+    pub(crate) fn append_part(
+        &mut self,
+        input: &str,
+        is_first: bool,
+        render_config: &RenderConfig,
+        slugger: &mut Slugger,
+        outline: &mut Outline,
+    ) -> anyhow::Result<()> {
+        let highlight_context = render_config.highlight_context();
+        let theme_name = self.code_blocks.configure(render_config);
+        let syntect_ctx = SyntectCtx {
+            highlight_context: &highlight_context,
+            theme_name: &theme_name,
+            code_blocks: &self.code_blocks,
+        };
+        load_css();
+
+        // Only the first part can start with front matter
+        let prefixed;
+        let input = if is_first {
+            input
+        } else {
+            prefixed = format!("\n{input}");
+            prefixed.as_str()
+        };
+        let tree = markdown::to_mdast(input, &render_config.parse_options)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| "commonmark parsing error")?;
+        let children = tree.children().map_or(&[][..], Vec::as_slice);
+        let mut definitions = HashMap::new();
+        collect_definitions(children, &mut definitions);
+        let doc_ctx = DocumentCtx {
+            syntect_ctx,
+            render_config,
+            definitions,
+            anchors: self.anchors.clone(),
+            slugger: RefCell::new(std::mem::take(slugger)),
+            outline: RefCell::new(std::mem::take(outline)),
+            footnotes: RefCell::new(Footnotes::collect(children)),
+            inline_formulas: RefCell::default(),
+        };
+        let front_matter = FrontMatter::from_nodes(children);
+        self.blocks.append(
+            input,
+            children,
+            &self.content_box,
+            front_matter.as_ref(),
+            &doc_ctx,
+        );
+        if is_first {
+            self.front_matter = front_matter;
         }
+        *slugger = doc_ctx.slugger.take();
+        *outline = doc_ctx.outline.take();
+
+        Ok(())
+    }
+
+    /// Number of top-level blocks rendered.
+    pub(crate) fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Remove the top-level blocks after the first `len` ones.
+    pub(crate) fn truncate_blocks(&mut self, len: usize) {
+        self.blocks.truncate(len, &self.content_box);
+    }
+
+    /// Replace the headings of the outline, updating its model in place.
+    pub(crate) fn set_outline(&mut self, outline: Outline) {
+        self.outline.replace(outline);
     }
 }
 
-/// Create widgets from commonmark input and return them in a new `gtk::Viewport`.
+/// Create widgets from commonmark input and return them in a [`RenderedDocument`], along with the
+/// document metadata.
+///
+/// The render configuration is borrowed, so that it can be given again to
+/// [`RenderedDocument::update`].
 ///
 /// ## Errors
 ///
@@ -82,21 +305,12 @@ impl Default for RenderConfig<'_> {
 ///
 /// Warning logs will be emitted if:
 ///
-/// - a code block language name is invalid
-/// - the provided syntect theme name is invalid
-///
-/// For available themes, please refer to the [syntect](https://github.com/trishume/syntect) documentation.
-pub fn render_input(input: &str, render_config: RenderConfig) -> anyhow::Result<gtk::Viewport> {
-    // Init synctect
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-    let syntect_ctx = SyntectCtx {
-        ps: &ps,
-        ts: &ts,
-        theme_name: render_config.highlight_theme,
-    };
-    load_css();
-
+/// - a code block language name or meta string is invalid
+/// - the front matter cannot be parsed
+pub fn render_document(
+    input: &str,
+    render_config: &RenderConfig,
+) -> anyhow::Result<RenderedDocument> {
     // Init viewport and content box
     let content_box = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
@@ -115,23 +329,58 @@ pub fn render_input(input: &str, render_config: RenderConfig) -> anyhow::Result<
         .build();
     viewport.set_child(Some(&content_box));
 
-    // Read commonmark
-    let tree = markdown::to_mdast(input, &render_config.parse_options)
-        .map_err(anyhow::Error::msg)
-        .with_context(|| "commonmark parsing error")?;
-    if let Some(children) = tree.children() {
-        append_widgets_from_children(
-            children,
-            &content_box,
-            None,
-            &syntect_ctx,
-            &mut 0,
-            None,
-            &render_config,
-        );
-    }
+    let mut document = RenderedDocument {
+        viewport,
+        content_box,
+        front_matter: None,
+        anchors: Anchors::default(),
+        outline: Outline::default(),
+        blocks: Blocks::default(),
+        code_blocks: CodeBlocks::default(),
+    };
+    document.update(input, render_config)?;
+    document.code_blocks.follow_color_scheme(&document.viewport);
+    Ok(document)
+}
 
-    Ok(viewport)
+#[doc(hidden)]
+pub mod __private {
+    pub use gtk::glib;
+}
+
+/// Embed images at compilation time, for use with [`ImageSetting::IncludeBytes`].
+///
+/// Each image is given as the URL used in the markdown input, optionally followed by the path of
+/// the file to embed, relative to the current source file, if it differs from the URL.
+///
+/// ## Example
+///
+/// ```rust,ignore
+/// use gtk4_commonmark::{include_images, ImageSetting, RenderConfig};
+///
+/// let config = RenderConfig::builder()
+///     .image_settings(ImageSetting::IncludeBytes(include_images!(
+///         "./examples/img/rust.png" => "../examples/img/rust.png",
+///     )))
+///     .build()
+///     .unwrap();
+/// ```
+#[macro_export]
+macro_rules! include_images {
+    ($($url:literal $(=> $path:literal)?),* $(,)?) => {{
+        let mut images = ::std::collections::HashMap::new();
+        $(
+            images.insert(
+                $url.to_string(),
+                $crate::__private::glib::Bytes::from_static(
+                    include_bytes!($crate::include_images!(@path $url $(, $path)?)),
+                ),
+            );
+        )*
+        images
+    }};
+    (@path $url:literal) => { $url };
+    (@path $url:literal, $path:literal) => { $path };
 }
 
 /// Append a string to a label
@@ -139,33 +388,93 @@ fn label_append(label: &gtk::Label, text: &str) {
     label.set_label(&format!("{}{}", label.label(), text));
 }
 
+/// Collect the definitions of the whole tree by normalized identifier. The first definition of an
+/// identifier takes precedence.
+fn collect_definitions<'a>(nodes: &'a [Node], definitions: &mut HashMap<String, &'a Definition>) {
+    for node in nodes {
+        if let Node::Definition(definition) = node {
+            definitions
+                .entry(normalize_identifier(&definition.identifier))
+                .or_insert(definition);
+        }
+        if let Some(children) = node.children() {
+            collect_definitions(children, definitions);
+        }
+    }
+}
+
+/// Normalize a reference or definition identifier so that they can be matched, as CommonMark
+/// requires: whitespace is collapsed and trimmed, and case is folded.
+fn normalize_identifier(identifier: &str) -> String {
+    identifier
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .to_uppercase()
+}
+
 struct SyntectCtx<'a> {
-    ps: &'a SyntaxSet,
-    ts: &'a ThemeSet,
+    highlight_context: &'a HighlightContext,
+    /// Theme of the current color scheme
     theme_name: &'a str,
+    code_blocks: &'a CodeBlocks,
+}
+
+/// Document-wide state shared by every rendered node.
+struct DocumentCtx<'a> {
+    syntect_ctx: SyntectCtx<'a>,
+    render_config: &'a RenderConfig<'a>,
+    /// Link and image definitions by normalized identifier
+    definitions: HashMap<String, &'a Definition>,
+    /// Widgets that in-document `#anchor` links can scroll to
+    anchors: Anchors,
+    slugger: RefCell<Slugger>,
+    /// Headings rendered so far
+    outline: RefCell<Outline>,
+    footnotes: RefCell<Footnotes<'a>>,
+    /// Inline math of the labels being rendered
+    inline_formulas: RefCell<InlineFormulas>,
+}
+
+/// State of the list currently being rendered.
+#[derive(Default)]
+struct ListContext {
+    indent_level: u16,
+    /// Number of the next item if the list is ordered
+    next_number: Option<u32>,
+    numbering: ListNumbering,
+    /// Width of the widest item marker of the list, in characters
+    marker_width_chars: i32,
 }
 
 #[derive(Clone)]
 struct TableContext<'a> {
     table_grid: &'a gtk::Grid,
+    /// Alignment of each column
+    align: &'a [AlignKind],
     current_row: i32,
     current_column: i32,
 }
 
 /// Append widgets to the root `gtk::Box`.
-fn append_widgets_from_children<'a>(
+fn append_widgets_from_children(
     children: &[Node],
     root: &gtk::Box,
     current_label: Option<&gtk::Label>,
-    syntect_ctx: &SyntectCtx<'a>,
-    list_indent_level: &mut u16,
+    doc_ctx: &DocumentCtx,
+    list_ctx: &mut ListContext,
     table_ctx: Option<TableContext>,
-    render_config: &RenderConfig,
 ) {
     let mut created_labels: Vec<gtk::Label> = Vec::new();
     let mut table_ctx = table_ctx;
+    // Closing markup of the inline HTML tags opened by these children
+    let mut open_html_tags = Vec::new();
 
-    for child in children {
+    // Siblings are visited by index, as block HTML can wrap the siblings that follow it
+    let mut index = 0;
+    while let Some(child) = children.get(index) {
+        index += 1;
         match child {
             Node::Heading(heading) => {
                 // A heading is a box with a label and a horizontal separator
@@ -197,14 +506,20 @@ fn append_widgets_from_children<'a>(
                 heading_box.append(&label);
                 heading_box.append(&separator);
                 root.append(&heading_box);
+                let text = child.to_string();
+                let slug = doc_ctx.slugger.borrow_mut().slug(&text);
+                doc_ctx
+                    .outline
+                    .borrow_mut()
+                    .push(heading.depth, text.trim(), &slug, &heading_box);
+                doc_ctx.anchors.insert(slug, &heading_box);
                 append_widgets_from_children(
                     &heading.children,
                     &heading_box,
                     Some(&label),
-                    syntect_ctx,
-                    list_indent_level,
+                    doc_ctx,
+                    list_ctx,
                     None,
-                    render_config,
                 );
                 label_append(&label, "</span>");
                 created_labels.push(label);
@@ -220,10 +535,9 @@ fn append_widgets_from_children<'a>(
                         &node.children,
                         root,
                         Some(label),
-                        syntect_ctx,
-                        list_indent_level,
+                        doc_ctx,
+                        list_ctx,
                         None,
-                        render_config,
                     );
                 } else {
                     let paragraph_box = gtk::Box::builder()
@@ -237,10 +551,9 @@ fn append_widgets_from_children<'a>(
                         &node.children,
                         &paragraph_box,
                         Some(&paragraph_label),
-                        syntect_ctx,
-                        list_indent_level,
+                        doc_ctx,
+                        list_ctx,
                         None,
-                        render_config,
                     );
                     root.append(&paragraph_box);
                     created_labels.push(paragraph_label);
@@ -268,10 +581,9 @@ fn append_widgets_from_children<'a>(
                     &block_quote.children,
                     &block_quote_inner_box,
                     None,
-                    syntect_ctx,
-                    list_indent_level,
+                    doc_ctx,
+                    list_ctx,
                     None,
-                    render_config,
                 );
                 block_quote_outer_box.append(&block_quote_inner_box);
                 root.append(&block_quote_outer_box);
@@ -283,10 +595,9 @@ fn append_widgets_from_children<'a>(
                         &node.children,
                         root,
                         Some(label),
-                        syntect_ctx,
-                        list_indent_level,
+                        doc_ctx,
+                        list_ctx,
                         None,
-                        render_config,
                     );
                     label_append(label, "</b>");
                 }
@@ -298,10 +609,9 @@ fn append_widgets_from_children<'a>(
                         &node.children,
                         root,
                         Some(label),
-                        syntect_ctx,
-                        list_indent_level,
+                        doc_ctx,
+                        list_ctx,
                         None,
-                        render_config,
                     );
                     label_append(label, "</i>");
                 }
@@ -317,31 +627,62 @@ fn append_widgets_from_children<'a>(
                     .name("commonmark_list_box")
                     .build();
                 root.append(&list_box);
-                *list_indent_level += 1;
+
+                // Nested lists get their own numbering, the parent one is restored once they are rendered
+                let indent_level = list_ctx.indent_level + 1;
+                let list_numbering = &doc_ctx.render_config.list_numbering;
+                let mut numbering =
+                    list_numbering[list_ctx.indent_level as usize % list_numbering.len()];
+                let (next_number, marker_width_chars) = if list.ordered {
+                    let start = list.start.unwrap_or(1);
+                    let last = start + (list.children.len() as u32).saturating_sub(1);
+                    numbering = numbering.for_list(start..=last);
+                    let marker_width_chars = (start..=last)
+                        .map(|n| numbering.format(n).chars().count() as i32)
+                        .max()
+                        .unwrap_or_default();
+                    (Some(start), marker_width_chars)
+                } else {
+                    (None, 0)
+                };
+                let parent_list_ctx = std::mem::replace(
+                    list_ctx,
+                    ListContext {
+                        indent_level,
+                        next_number,
+                        numbering,
+                        marker_width_chars,
+                    },
+                );
                 append_widgets_from_children(
                     &list.children,
                     &list_box,
                     None,
-                    syntect_ctx,
-                    list_indent_level,
+                    doc_ctx,
+                    list_ctx,
                     None,
-                    render_config,
                 );
-                *list_indent_level -= 1;
+                *list_ctx = parent_list_ctx;
             }
             Node::ListItem(item) => {
                 let item_outer_box = gtk::Box::builder()
                     .orientation(gtk::Orientation::Horizontal)
-                    .margin_start(((*list_indent_level as i32) - 1) * 15)
+                    .margin_start(((list_ctx.indent_level as i32) - 1) * 15)
                     .valign(gtk::Align::Start)
                     .build();
-                item_outer_box.append(
-                    &gtk::Label::builder()
-                        .label("- ")
-                        .margin_top(3) // to align with checkbox
-                        .valign(gtk::Align::Start)
-                        .build(),
-                );
+                let marker_label = gtk::Label::builder()
+                    .label("- ")
+                    .margin_top(3) // to align with checkbox
+                    .valign(gtk::Align::Start)
+                    .build();
+                if let Some(number) = list_ctx.next_number {
+                    // Right-align numbers so that multi-digit items line up
+                    marker_label.set_label(&format!("{} ", list_ctx.numbering.format(number)));
+                    marker_label.set_width_chars(list_ctx.marker_width_chars + 1);
+                    marker_label.set_xalign(1.0);
+                    list_ctx.next_number = Some(number + 1);
+                }
+                item_outer_box.append(&marker_label);
                 if let Some(checked) = item.checked {
                     let checked = gtk::CheckButton::builder()
                         .active(checked)
@@ -362,10 +703,9 @@ fn append_widgets_from_children<'a>(
                     &item.children,
                     &item_inner_box,
                     None,
-                    syntect_ctx,
-                    list_indent_level,
+                    doc_ctx,
+                    list_ctx,
                     None,
-                    render_config,
                 );
             }
             Node::InlineCode(inline_code) => {
@@ -386,10 +726,9 @@ fn append_widgets_from_children<'a>(
                         &node.children,
                         root,
                         Some(label),
-                        syntect_ctx,
-                        list_indent_level,
+                        doc_ctx,
+                        list_ctx,
                         None,
-                        render_config,
                     );
                     label_append(label, "</s>");
                 }
@@ -397,32 +736,59 @@ fn append_widgets_from_children<'a>(
             Node::Code(code_node) => {
                 parse_code_block(
                     code_node.lang.as_ref(),
-                    syntect_ctx.ps,
-                    syntect_ctx.ts,
-                    syntect_ctx.theme_name,
+                    code_node.meta.as_deref(),
                     &code_node.value,
                     root,
+                    doc_ctx,
                 );
             }
             Node::Link(link) => {
                 if let Some(link_label) = current_label {
-                    label_append(
+                    append_link(
                         link_label,
-                        &format!("<u><a href=\"{}\">", html_escape(&link.url)),
+                        &link.url,
+                        link.title.as_deref(),
+                        &link.children,
+                        root,
+                        doc_ctx,
+                        list_ctx,
                     );
-                    if let Some(title) = &link.title {
-                        label_append(link_label, &format!("{}</a></u>", html_escape(title)));
+                }
+            }
+            Node::LinkReference(reference) => {
+                if let Some(link_label) = current_label {
+                    let identifier = normalize_identifier(&reference.identifier);
+                    if let Some(definition) = doc_ctx.definitions.get(&identifier) {
+                        append_link(
+                            link_label,
+                            &definition.url,
+                            definition.title.as_deref(),
+                            &reference.children,
+                            root,
+                            doc_ctx,
+                            list_ctx,
+                        );
                     } else {
+                        // Unresolved references are rendered as their source text
+                        label_append(link_label, "[");
                         append_widgets_from_children(
-                            &link.children,
+                            &reference.children,
                             root,
                             Some(link_label),
-                            syntect_ctx,
-                            list_indent_level,
+                            doc_ctx,
+                            list_ctx,
                             None,
-                            render_config,
                         );
-                        label_append(link_label, "</a></u>");
+                        label_append(
+                            link_label,
+                            &html_escape(&format!(
+                                "]{}",
+                                reference_suffix(
+                                    &reference.reference_kind,
+                                    reference.label.as_deref()
+                                )
+                            )),
+                        );
                     }
                 }
             }
@@ -432,14 +798,14 @@ fn append_widgets_from_children<'a>(
                     &table.children,
                     root,
                     None,
-                    syntect_ctx,
-                    list_indent_level,
+                    doc_ctx,
+                    list_ctx,
                     Some(TableContext {
                         table_grid: &table_grid,
+                        align: &table.align,
                         current_row: 0,
                         current_column: 0,
                     }),
-                    render_config,
                 );
 
                 root.append(&table_grid);
@@ -452,10 +818,9 @@ fn append_widgets_from_children<'a>(
                         &table_row.children,
                         root,
                         None,
-                        syntect_ctx,
-                        list_indent_level,
+                        doc_ctx,
+                        list_ctx,
                         Some(ctx.clone()),
-                        render_config,
                     );
                     table_ctx = Some(ctx);
                 }
@@ -469,6 +834,17 @@ fn append_widgets_from_children<'a>(
                     cell_label.set_margin_top(4);
                     cell_label.set_hexpand(true);
                     cell_label.set_vexpand(true);
+                    let (halign, justify, xalign) = match ctx.align.get(ctx.current_column as usize)
+                    {
+                        Some(AlignKind::Center) => {
+                            (gtk::Align::Center, gtk::Justification::Center, 0.5)
+                        }
+                        Some(AlignKind::Right) => (gtk::Align::End, gtk::Justification::Right, 1.0),
+                        _ => (gtk::Align::Start, gtk::Justification::Left, 0.0),
+                    };
+                    cell_label.set_halign(halign);
+                    cell_label.set_justify(justify);
+                    cell_label.set_xalign(xalign);
                     let cell_outer_box = gtk::Box::builder()
                         .orientation(gtk::Orientation::Vertical)
                         .spacing(0)
@@ -483,6 +859,14 @@ fn append_widgets_from_children<'a>(
                         .margin_top(1)
                         .spacing(5)
                         .build();
+                    // The first row is the header
+                    let is_header = ctx.current_row == 1;
+                    if is_header {
+                        cell_inner_box.add_css_class("table_header_cell");
+                        label_append(&cell_label, "<b>");
+                    } else if doc_ctx.render_config.table_striping && ctx.current_row % 2 == 1 {
+                        cell_inner_box.add_css_class("table_striped_cell");
+                    }
                     cell_inner_box.append(&cell_label);
                     cell_outer_box.append(&cell_inner_box);
                     ctx.table_grid.attach(
@@ -498,11 +882,13 @@ fn append_widgets_from_children<'a>(
                         &table_cell.children,
                         &cell_inner_box,
                         Some(&cell_label),
-                        syntect_ctx,
-                        list_indent_level,
+                        doc_ctx,
+                        list_ctx,
                         Some(ctx.clone()),
-                        render_config,
                     );
+                    if is_header {
+                        label_append(&cell_label, "</b>");
+                    }
                     table_ctx = Some(ctx);
                     created_labels.push(cell_label);
                 }
@@ -511,29 +897,69 @@ fn append_widgets_from_children<'a>(
                 let sep = gtk::Separator::new(gtk::Orientation::Horizontal);
                 root.append(&sep);
             }
-            Node::Image(image) => match render_config.image_settings {
-                ImageSetting::Ignore => continue,
-                ImageSetting::FromPath => {
-                    let picture = gtk::Picture::for_filename(&image.url);
-                    picture.set_hexpand(true);
-                    picture.set_vexpand(true);
-                    picture.set_can_shrink(true);
-                    root.append(&picture);
+            Node::Image(image) => {
+                append_image(&image.url, image.title.as_deref(), root, doc_ctx);
+            }
+            Node::ImageReference(reference) => {
+                let identifier = normalize_identifier(&reference.identifier);
+                if let Some(definition) = doc_ctx.definitions.get(&identifier) {
+                    append_image(&definition.url, definition.title.as_deref(), root, doc_ctx);
+                } else if let Some(label) = current_label {
+                    // Unresolved references are rendered as their source text
+                    label_append(
+                        label,
+                        &html_escape(&format!(
+                            "![{}]{}",
+                            reference.alt,
+                            reference_suffix(&reference.reference_kind, reference.label.as_deref())
+                        )),
+                    );
                 }
-                ImageSetting::IncludeBytes => todo!(),
-            },
-            // Nodes below are not currently supported
-            Node::FootnoteReference(_) => {}
-            Node::LinkReference(_) => {}
+            }
+            Node::InlineMath(math) => {
+                if let Some(label) = current_label {
+                    let markup = doc_ctx
+                        .inline_formulas
+                        .borrow_mut()
+                        .markup(label, &math.value);
+                    label_append(label, &markup);
+                }
+            }
+            Node::Math(math) => {
+                root.append(&math::block_widget(&math.value));
+            }
+            Node::Html(html) => {
+                if let Some(label) = current_label {
+                    label_append(
+                        label,
+                        &html::inline_markup(
+                            &html.value,
+                            &doc_ctx.render_config.inline_html_tags,
+                            &mut open_html_tags,
+                        ),
+                    );
+                } else {
+                    index = html::append_block(children, index - 1, root, doc_ctx, list_ctx);
+                }
+            }
+            // Front matter is rendered before the rest of the document
+            Node::Toml(_) | Node::Yaml(_) => {}
+            // Definitions are collected before rendering and used by references
             Node::Definition(_) => {}
-            Node::ImageReference(_) => {}
-            Node::Math(_) => {}
-            Node::InlineMath(_) => {}
-            Node::Html(_) => {}
-            Node::MdxjsEsm(_) => {}
-            Node::Toml(_) => {}
-            Node::Yaml(_) => {}
+            Node::FootnoteReference(reference) => {
+                if let Some(label) = current_label {
+                    footnotes::append_reference(
+                        label,
+                        &reference.identifier,
+                        reference.label.as_deref(),
+                        doc_ctx,
+                    );
+                }
+            }
+            // Footnote definitions are rendered at the end of the document
             Node::FootnoteDefinition(_) => {}
+            // Nodes below are not currently supported
+            Node::MdxjsEsm(_) => {}
             Node::MdxJsxFlowElement(_) => {}
             Node::MdxJsxTextElement(_) => {}
             Node::MdxTextExpression(_) => {}
@@ -542,8 +968,166 @@ fn append_widgets_from_children<'a>(
         }
     }
 
+    // Close the inline HTML tags left open to keep the markup well-formed
+    if let Some(label) = current_label {
+        for (_, closing) in open_html_tags.into_iter().rev() {
+            label_append(label, closing);
+        }
+    }
+
     for created_label in &created_labels {
         created_label.set_use_markup(true);
+        links::connect_label(created_label, doc_ctx);
+        doc_ctx.inline_formulas.borrow_mut().attach(created_label);
+    }
+}
+
+/// Append a link to a label. If the link has a title, it is displayed instead of the link children.
+fn append_link(
+    link_label: &gtk::Label,
+    url: &str,
+    title: Option<&str>,
+    children: &[Node],
+    root: &gtk::Box,
+    doc_ctx: &DocumentCtx,
+    list_ctx: &mut ListContext,
+) {
+    // Links refused by the sandbox are rendered as plain text
+    let Some(url) = urls::resolve(url, doc_ctx.render_config) else {
+        match title {
+            Some(title) => label_append(link_label, &html_escape(title)),
+            None => append_widgets_from_children(
+                children,
+                root,
+                Some(link_label),
+                doc_ctx,
+                list_ctx,
+                None,
+            ),
+        }
+        return;
+    };
+    label_append(
+        link_label,
+        &format!("<u><a href=\"{}\">", html_escape(&url)),
+    );
+    if let Some(title) = title {
+        label_append(link_label, &format!("{}</a></u>", html_escape(title)));
+    } else {
+        append_widgets_from_children(children, root, Some(link_label), doc_ctx, list_ctx, None);
+        label_append(link_label, "</a></u>");
+    }
+}
+
+/// Append an image to the root `gtk::Box`, according to the image settings. The title, if any, is
+/// shown as a tooltip.
+fn append_image(url: &str, title: Option<&str>, root: &gtk::Box, doc_ctx: &DocumentCtx) {
+    if let Some(picture) = create_picture(url, None, doc_ctx) {
+        picture.set_tooltip_text(title);
+        root.append(&picture);
+    }
+}
+
+/// Create a picture according to the image settings, or `None` if images are ignored.
+///
+/// If a size is given, the image is scaled to it instead of expanding, a dimension of `-1` keeping
+/// the aspect ratio.
+fn create_picture(
+    url: &str,
+    size: Option<(i32, i32)>,
+    doc_ctx: &DocumentCtx,
+) -> Option<gtk::Picture> {
+    let texture = match &doc_ctx.render_config.image_settings {
+        ImageSetting::Ignore => return None,
+        ImageSetting::FromPath => {
+            let url = urls::resolve(url, doc_ctx.render_config)?;
+            // Remote files would be read synchronously, blocking the main thread
+            let file = match urls::scheme(&url).as_deref() {
+                Some("file" | "resource") => gio::File::for_uri(&url),
+                Some(_) => {
+                    log::warn!("not showing {url}, remote images require an image loader");
+                    return None;
+                }
+                None => gio::File::for_path(&url),
+            };
+            match size {
+                Some((width, height)) => file
+                    .read(gio::Cancellable::NONE)
+                    .and_then(|stream| {
+                        gdk_pixbuf::Pixbuf::from_stream_at_scale(
+                            &stream,
+                            width,
+                            height,
+                            true,
+                            gio::Cancellable::NONE,
+                        )
+                    })
+                    .map(|pixbuf| gdk::Texture::for_pixbuf(&pixbuf)),
+                None => {
+                    let picture = gtk::Picture::for_file(&file);
+                    picture.set_hexpand(true);
+                    picture.set_vexpand(true);
+                    picture.set_can_shrink(true);
+                    return Some(picture);
+                }
+            }
+        }
+        ImageSetting::Loader(loader) => {
+            let url = urls::resolve(url, doc_ctx.render_config)?;
+            let picture = images::loading_picture(&url, loader.as_ref());
+            match size {
+                Some((width, height)) => {
+                    picture.set_size_request(width, height);
+                    picture.set_can_shrink(true);
+                }
+                None => {
+                    picture.set_hexpand(true);
+                    picture.set_vexpand(true);
+                    picture.set_can_shrink(true);
+                }
+            }
+            return Some(picture);
+        }
+        // Embedded images are looked up by URL as written in the input
+        ImageSetting::IncludeBytes(images) => {
+            let Some(bytes) = urls::embedded_image(images, url) else {
+                log::warn!("no embedded image for {url}");
+                return None;
+            };
+            match size {
+                Some((width, height)) => gdk_pixbuf::Pixbuf::from_stream_at_scale(
+                    &gio::MemoryInputStream::from_bytes(bytes),
+                    width,
+                    height,
+                    true,
+                    gio::Cancellable::NONE,
+                )
+                .map(|pixbuf| gdk::Texture::for_pixbuf(&pixbuf)),
+                None => gdk::Texture::from_bytes(bytes),
+            }
+        }
+    };
+
+    let picture = match texture {
+        Ok(texture) => gtk::Picture::for_paintable(&texture),
+        Err(err) => {
+            log::warn!("unable to load image {url}: {err}");
+            gtk::Picture::new()
+        }
+    };
+    let expand = size.is_none();
+    picture.set_hexpand(expand);
+    picture.set_vexpand(expand);
+    picture.set_can_shrink(expand);
+    Some(picture)
+}
+
+/// Source text following the closing bracket of an unresolved reference, which depends on its kind.
+fn reference_suffix(reference_kind: &ReferenceKind, label: Option<&str>) -> String {
+    match reference_kind {
+        ReferenceKind::Shortcut => String::new(),
+        ReferenceKind::Collapsed => "[]".to_string(),
+        ReferenceKind::Full => format!("[{}]", label.unwrap_or_default()),
     }
 }
 
@@ -557,20 +1141,31 @@ fn empty_gtk_label() -> gtk::Label {
         .build()
 }
 
-/// Converts a code block to widgets that are appended to the root `gtk::Box`. Code is syntax highlighted.
+/// Converts a code block to widgets that are appended to the root `gtk::Box`, and returns the outer
+/// box of the code block. Code is syntax highlighted.
+///
+/// ## Logging
+///
+/// Warning logs are emitted for the invalid parts of the meta string.
 fn parse_code_block(
     language_name: Option<&String>,
-    ps: &SyntaxSet,
-    ts: &ThemeSet,
-    highlight_theme_name: &str,
+    meta: Option<&str>,
     content: &str,
     root: &gtk::Box,
-) {
+    doc_ctx: &DocumentCtx,
+) -> gtk::Box {
+    let syntect_ctx = &doc_ctx.syntect_ctx;
+    let meta = meta.map(CodeMeta::parse).unwrap_or_default();
     let outer_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
         .css_classes(vec!["code_block_box"])
         .margin_bottom(10)
         .margin_top(10)
         .build();
+    if let Some(title) = &meta.title {
+        let language_name = language_name.filter(|_| doc_ctx.render_config.code_language_badge);
+        outer_box.append(&code_block_header(title, language_name));
+    }
     let code_block_box = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
         .margin_bottom(10)
@@ -580,22 +1175,196 @@ fn parse_code_block(
         .hexpand(false)
         .build();
 
-    let syntax_opt = language_name.and_then(|l| ps.find_syntax_by_token(l).or_else(|| None));
-    let theme_opt = &ts.themes.get(highlight_theme_name);
-    if theme_opt.is_none() {
-        log::warn!("unknown theme name: {}", highlight_theme_name);
+    let lines = highlight_lines(
+        language_name.map(String::as_str),
+        syntect_ctx.highlight_context,
+        syntect_ctx.theme_name,
+        content,
+    );
+    let gutter_width = lines.len().to_string().len() as i32;
+    // Lines cannot wrap next to line numbers, which would no longer match them
+    let scrolls = meta.show_line_numbers || doc_ctx.render_config.code_block_scrolling;
+    let mut labels = Vec::new();
+    // Highlighted lines are rendered apart, so that their background spans the whole block
+    for (range, highlighted) in meta.segments(lines.len()) {
+        let segment = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        if highlighted {
+            segment.add_css_class("code_block_highlighted_line");
+        }
+        if meta.show_line_numbers {
+            let numbers: Vec<String> = (range.start + 1..=range.end)
+                .map(|number| number.to_string())
+                .collect();
+            segment.append(
+                &gtk::Label::builder()
+                    .use_markup(true)
+                    .label(format!("<tt>{}</tt>", numbers.join("\n")))
+                    .width_chars(gutter_width)
+                    .xalign(1.0)
+                    .valign(gtk::Align::Start)
+                    .css_classes(vec!["code_block_gutter"])
+                    .build(),
+            );
+        }
+        let label = gtk::Label::builder()
+            .use_markup(true)
+            .justify(gtk::Justification::Left)
+            .halign(gtk::Align::Start)
+            .selectable(true)
+            .wrap(!scrolls)
+            .focusable(false)
+            .label(code_markup(&lines[range.clone()]))
+            .build();
+        segment.append(&label);
+        code_block_box.append(&segment);
+        labels.push((label, range));
+    }
+    if doc_ctx.render_config.code_block_colors == CodeBlockColors::Theme {
+        outer_box.add_css_class(
+            &syntect_ctx
+                .highlight_context
+                .theme_css_class(syntect_ctx.theme_name),
+        );
+    }
+    syntect_ctx.code_blocks.register(
+        &outer_box,
+        labels,
+        language_name.map(String::as_str),
+        content,
+    );
+
+    let max_height = doc_ctx.render_config.code_block_max_height;
+    let mut expander = None;
+    let body: gtk::Widget = if scrolls || max_height.is_some() {
+        let scrolled_window = gtk::ScrolledWindow::builder()
+            .hscrollbar_policy(if scrolls {
+                gtk::PolicyType::Automatic
+            } else {
+                gtk::PolicyType::Never
+            })
+            .vscrollbar_policy(gtk::PolicyType::Never)
+            .propagate_natural_height(true)
+            .child(&code_block_box)
+            .build();
+        expander = max_height.map(|max_height| collapse_expander(&scrolled_window, max_height));
+        scrolled_window.upcast()
+    } else {
+        code_block_box.upcast()
+    };
+    outer_box.append(&code_overlay(
+        &body,
+        language_name.map(String::as_str),
+        meta.title.is_some(),
+        content,
+        doc_ctx.render_config,
+    ));
+    if let Some(expander) = expander {
+        outer_box.append(&expander);
+    }
+    root.append(&outer_box);
+    outer_box
+}
+
+/// Limit the height of a code block to the given maximum, and return an expander showing the
+/// whole block, only visible if the block is taller.
+fn collapse_expander(scrolled_window: &gtk::ScrolledWindow, max_height: i32) -> gtk::Expander {
+    scrolled_window.set_max_content_height(max_height);
+    scrolled_window.set_vscrollbar_policy(gtk::PolicyType::External);
+    let expander = gtk::Expander::builder()
+        .label("Show more")
+        .visible(false)
+        .margin_start(10)
+        .margin_bottom(6)
+        .css_classes(vec!["code_block_expander"])
+        .build();
+
+    let collapsed_window = scrolled_window.downgrade();
+    expander.connect_expanded_notify(move |expander| {
+        let Some(scrolled_window) = collapsed_window.upgrade() else {
+            return;
+        };
+        if expander.is_expanded() {
+            scrolled_window.set_vscrollbar_policy(gtk::PolicyType::Never);
+            scrolled_window.set_max_content_height(-1);
+            expander.set_label(Some("Show less"));
+        } else {
+            scrolled_window.set_max_content_height(max_height);
+            scrolled_window.set_vscrollbar_policy(gtk::PolicyType::External);
+            expander.set_label(Some("Show more"));
+        }
+    });
+    // The height of the block is only known once it is allocated
+    let weak_expander = expander.downgrade();
+    scrolled_window
+        .vadjustment()
+        .connect_changed(move |adjustment| {
+            if let Some(expander) = weak_expander.upgrade() {
+                let overflows = adjustment.upper() > adjustment.page_size() + 1.0;
+                expander.set_visible(overflows || expander.is_expanded());
+            }
+        });
+    expander
+}
+
+/// Header of a code block, with its file name and optionally its language.
+fn code_block_header(title: &str, language_name: Option<&String>) -> gtk::Box {
+    let header = gtk::Box::builder()
+        .spacing(10)
+        .css_classes(vec!["code_block_header"])
+        .build();
+    header.append(
+        &gtk::Label::builder()
+            .label(title)
+            .halign(gtk::Align::Start)
+            .hexpand(true)
+            .ellipsize(gtk::pango::EllipsizeMode::Middle)
+            .css_classes(vec!["monospace"])
+            .build(),
+    );
+    if let Some(language_name) = language_name {
+        header.append(&language_badge(language_name));
     }
+    header
+}
+
+/// Pango markup of consecutive lines of a code block.
+fn code_markup(lines: &[String]) -> String {
+    format!("<tt>{}</tt>", lines.join("\n"))
+}
 
-    let content = if let (Some(syntax), Some(theme)) = (syntax_opt, theme_opt) {
-        let mut highlight_lines = HighlightLines::new(syntax, theme);
-        let mut pango_str = String::new();
-        for line in LinesWithEndings::from(content) {
+/// Pango markup of each line of a code block, syntax highlighted with the given theme. An empty
+/// code block has one empty line.
+fn highlight_lines(
+    language_name: Option<&str>,
+    highlight_context: &HighlightContext,
+    theme_name: &str,
+    content: &str,
+) -> Vec<String> {
+    if content.is_empty() {
+        return vec![String::new()];
+    }
+    let ps = highlight_context.syntax_set();
+    let syntax_opt = language_name.and_then(|l| highlight_context.find_syntax(l));
+    // The theme name has been validated when building the `RenderConfig`
+    let theme_opt = highlight_context.theme_set().themes.get(theme_name);
+    let (Some(syntax), Some(theme)) = (syntax_opt, theme_opt) else {
+        return content.lines().map(html_escape).collect();
+    };
+
+    let mut highlight_lines = HighlightLines::new(syntax, theme);
+    LinesWithEndings::from(content)
+        .map(|line| {
             let ranges: Vec<(Style, &str)> = match highlight_lines.highlight_line(line, ps) {
                 Ok(r) => r,
-                Err(_) => continue,
+                Err(_) => return html_escape(line.trim_end_matches(['\r', '\n'])),
             };
 
+            let mut pango_str = String::new();
             for (style, content) in ranges {
+                let content = content.trim_end_matches(['\r', '\n']);
+                if content.is_empty() {
+                    continue;
+                }
                 let foreground = style.foreground;
                 let (bold_start, bold_end) = if style
                     .font_style
@@ -636,28 +1405,34 @@ fn parse_code_block(
                     underline_end,
                 ));
             }
-        }
-        pango_str
-    } else {
-        html_escape(content)
-    };
-    code_block_box.append(
-        &gtk::Label::builder()
-            .use_markup(true)
-            .justify(gtk::Justification::Left)
-            .halign(gtk::Align::Start)
-            .selectable(true)
-            .wrap(true)
-            .focusable(false)
-            .label(format!("<tt>{content}</tt>"))
-            .build(),
-    );
+            pango_str
+        })
+        .collect()
+}
 
-    outer_box.append(&code_block_box);
-    root.append(&outer_box);
+thread_local! {
+    /// Displays the stylesheet of the renderer has been added to
+    static STYLED_DISPLAYS: RefCell<Vec<gdk::Display>> = RefCell::default();
 }
 
+/// Add the stylesheet of the renderer to the default display, unless it was already added.
 fn load_css() {
+    let Some(display) = Display::default() else {
+        log::error!("unable to load CSS for commonmark renderer: could not connect to a display");
+        return;
+    };
+    let styled = STYLED_DISPLAYS.with(|displays| {
+        let mut displays = displays.borrow_mut();
+        if displays.contains(&display) {
+            return true;
+        }
+        displays.push(display.clone());
+        false
+    });
+    if styled {
+        return;
+    }
+
     // Load the CSS file and add it to the provider
     let provider = CssProvider::new();
     provider.load_from_data(
@@ -667,20 +1442,52 @@ fn load_css() {
         .table_inner_box {
             background: @theme_bg_color;
         }
+        .table_inner_box.table_header_cell {
+            background: mix(@theme_bg_color, @theme_fg_color, 0.1);
+        }
+        .table_inner_box.table_striped_cell {
+            background: mix(@theme_bg_color, @theme_fg_color, 0.04);
+        }
         .code_block_box {
             background: @shade_color;
             border-radius: 10px;
+        }
+        .front_matter_card {
+            background: @shade_color;
+            border-radius: 10px;
+            padding: 10px;
+        }
+        .front_matter_tag {
+            background: alpha(@theme_selected_bg_color, 0.3);
+            border-radius: 6px;
+            padding: 2px 8px;
+        }
+        .code_block_header {
+            padding: 6px 10px;
+            border-bottom: 1px solid mix(@theme_bg_color, @theme_fg_color, 0.15);
+        }
+        .code_block_gutter {
+            color: mix(@theme_fg_color, @theme_bg_color, 0.45);
+            padding-right: 10px;
+        }
+        .code_block_highlighted_line {
+            background: alpha(@theme_selected_bg_color, 0.25);
+        }
+        .code_block_copy_button {
+            opacity: 0.6;
+        }
+        .code_block_copy_button:hover {
+            opacity: 1;
+        }
+        .image_placeholder {
+            opacity: 0.5;
         }",
     );
 
     // Add the provider to the default screen
-    if let Some(display) = Display::default() {
-        StyleContext::add_provider_for_display(
-            &display,
-            &provider,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-        );
-    } else {
-        log::error!("unable to load CSS for commonmark renderer: could not connect to a display")
-    }
+    StyleContext::add_provider_for_display(
+        &display,
+        &provider,
+        gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    );
 }
@@ -0,0 +1,188 @@
+//! Handling of activated links: the host application hook, in-document anchors and the default
+//! link policy.
+
+use std::{cell::Cell, collections::HashSet, fmt, rc::Rc};
+
+use gtk::{gdk, prelude::*};
+
+use crate::{urls::scheme, DocumentCtx};
+
+/// A link activated by the user, passed to the hook set with
+/// [`RenderConfigBuilder::on_link_activated`](crate::RenderConfigBuilder::on_link_activated).
+#[derive(Debug)]
+pub struct LinkActivation<'a> {
+    url: &'a str,
+    text: &'a str,
+    handled: Cell<bool>,
+}
+
+impl LinkActivation<'_> {
+    /// URL of the link, resolved against the base directory if it is relative.
+    pub fn url(&self) -> &str {
+        self.url
+    }
+
+    /// Text of the link.
+    pub fn text(&self) -> &str {
+        self.text
+    }
+
+    /// Mark the link as handled, so that it is neither scrolled to nor opened.
+    pub fn set_handled(&self) {
+        self.handled.set(true);
+    }
+
+    /// Whether the link was marked as handled.
+    pub fn is_handled(&self) -> bool {
+        self.handled.get()
+    }
+}
+
+/// Hook called when a link is activated.
+#[derive(Clone)]
+pub(crate) struct LinkHook(pub(crate) Rc<dyn Fn(&LinkActivation)>);
+
+impl fmt::Debug for LinkHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LinkHook")
+    }
+}
+
+/// How links that are not handled by the hook nor in-document anchors are opened.
+///
+/// The default policy launches any link with the default application.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkPolicy {
+    schemes: Option<HashSet<String>>,
+    confirm: bool,
+}
+
+impl LinkPolicy {
+    /// Policy launching any link with the default application.
+    pub fn launch() -> Self {
+        Self::default()
+    }
+
+    /// Only open links with one of the given schemes, such as `https` or `mailto`. Relative links
+    /// have the `file` scheme once resolved.
+    pub fn allow_schemes<I, S>(mut self, schemes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.schemes = Some(
+            schemes
+                .into_iter()
+                .map(|scheme| scheme.into().to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Set whether to ask for confirmation before opening a link.
+    pub fn confirm(mut self, confirm: bool) -> Self {
+        self.confirm = confirm;
+        self
+    }
+
+    /// Whether a link is allowed to be opened.
+    pub fn allows(&self, url: &str) -> bool {
+        match (&self.schemes, scheme(url)) {
+            (None, _) => true,
+            (Some(schemes), Some(scheme)) => schemes.contains(&scheme),
+            (Some(schemes), None) => schemes.contains("file"),
+        }
+    }
+}
+
+/// Handle the links of a label: the hook is called first, then `#anchor` links scroll to their
+/// anchor and other links are opened according to the link policy.
+pub(crate) fn connect_label(label: &gtk::Label, doc_ctx: &DocumentCtx) {
+    let anchors = doc_ctx.anchors.clone();
+    let hook = doc_ctx.render_config.on_link_activated.clone();
+    let policy = doc_ctx.render_config.link_policy.clone();
+    label.connect_activate_link(move |label, uri| {
+        if let Some(hook) = &hook {
+            let text = link_text(&label.label(), uri);
+            let activation = LinkActivation {
+                url: uri,
+                text: &text,
+                handled: Cell::new(false),
+            };
+            (hook.0)(&activation);
+            if activation.is_handled() {
+                return gtk::Inhibit(true);
+            }
+        }
+
+        if let Some(name) = uri.strip_prefix('#') {
+            if !anchors.scroll_to(name) {
+                log::warn!("unable to scroll to unknown anchor: {name}");
+            }
+            return gtk::Inhibit(true);
+        }
+        if !policy.allows(uri) {
+            log::warn!("refusing to open {uri}, whose scheme is not allowed");
+            return gtk::Inhibit(true);
+        }
+        if policy.confirm {
+            confirm_launch(label, uri);
+            return gtk::Inhibit(true);
+        }
+        gtk::Inhibit(false)
+    });
+}
+
+/// Ask for confirmation before launching a link with the default application.
+fn confirm_launch(label: &gtk::Label, uri: &str) {
+    let window = label
+        .root()
+        .and_then(|root| root.downcast::<gtk::Window>().ok());
+    let dialog = gtk::MessageDialog::builder()
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .text("Open link?")
+        .secondary_text(uri)
+        .build();
+    dialog.set_transient_for(window.as_ref());
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Open", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Cancel);
+
+    let uri = uri.to_string();
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            gtk::show_uri(dialog.transient_for().as_ref(), &uri, gdk::CURRENT_TIME);
+        }
+        dialog.close();
+    });
+    dialog.present();
+}
+
+/// Text of the first link to the URI in the markup of a label, without its markup.
+fn link_text(markup: &str, uri: &str) -> String {
+    let opening = format!("<a href=\"{}\">", html2pango::html_escape(uri));
+    let Some(start) = markup.find(&opening).map(|start| start + opening.len()) else {
+        return String::new();
+    };
+    let end = markup[start..]
+        .find("</a>")
+        .map_or(markup.len(), |end| start + end);
+
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in markup[start..end].chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
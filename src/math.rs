@@ -0,0 +1,1085 @@
+//! Rendering of a TeX subset for `$inline$` and `$$block$$` math, without any external tool.
+//!
+//! Math is laid out and drawn with cairo. Block math is drawn in a `gtk::DrawingArea`, while inline
+//! math is drawn over the space reserved for it in the text of its label, so that it flows with the
+//! surrounding paragraph. Sources using unsupported constructs are displayed as monospace text
+//! instead.
+
+use std::{collections::HashMap, iter::Peekable, str::Chars};
+
+use gtk::{cairo, pango, prelude::*};
+use html2pango::html_escape;
+
+/// Font family used to draw math.
+const MATH_FONT_FAMILY: &str = "serif";
+/// Font size of block math, in pixels.
+const BLOCK_FONT_SIZE: f64 = 20.0;
+/// Font size of inline math whose label has no font, in pixels.
+const INLINE_FONT_SIZE: f64 = 16.0;
+/// Padding around block math, in pixels.
+const BLOCK_PADDING: f64 = 4.0;
+/// Character standing for an inline formula in the text of a label.
+const FORMULA_PLACEHOLDER: char = '\u{FFFC}';
+
+/// A node of a parsed math expression.
+#[derive(Debug)]
+enum MathNode {
+    /// A letter, number, operator or symbol
+    Symbol {
+        text: String,
+        italic: bool,
+    },
+    /// Upright text, from `\text{...}` or function names such as `\sin`
+    Text(String),
+    Row(Vec<MathNode>),
+    Scripts {
+        base: Box<MathNode>,
+        sub: Option<Box<MathNode>>,
+        sup: Option<Box<MathNode>>,
+    },
+    Fraction(Box<MathNode>, Box<MathNode>),
+    Sqrt(Box<MathNode>),
+    /// An operator such as `\sum` or `\lim`, which can be drawn larger and with its scripts as limits
+    Operator {
+        text: String,
+        large: bool,
+        limits: bool,
+    },
+    /// Horizontal space, in em
+    Space(f64),
+    /// Content between `\left` and `\right` delimiters, which are stretched to its height
+    Fenced {
+        open: String,
+        body: Box<MathNode>,
+        close: String,
+    },
+}
+
+/// Token that ends the row being parsed.
+#[derive(PartialEq, Eq)]
+enum RowEnd {
+    Input,
+    Brace,
+    Right,
+}
+
+struct Parser<'s> {
+    chars: Peekable<Chars<'s>>,
+}
+
+/// Parse a TeX math source.
+///
+/// ## Errors
+///
+/// An error describing the first unsupported or invalid construct is returned.
+fn parse(source: &str) -> Result<MathNode, String> {
+    let mut parser = Parser {
+        chars: source.chars().peekable(),
+    };
+    parser.parse_row(RowEnd::Input).map(MathNode::Row)
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn parse_row(&mut self, end: RowEnd) -> Result<Vec<MathNode>, String> {
+        let mut row = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.chars.peek().copied() else {
+                return match end {
+                    RowEnd::Input => Ok(row),
+                    RowEnd::Brace => Err("missing closing brace".to_string()),
+                    RowEnd::Right => Err("missing `\\right`".to_string()),
+                };
+            };
+            match c {
+                '}' => {
+                    self.chars.next();
+                    return match end {
+                        RowEnd::Brace => Ok(row),
+                        _ => Err("unexpected closing brace".to_string()),
+                    };
+                }
+                '^' | '_' => {
+                    self.chars.next();
+                    let script = self.parse_atom()?;
+                    let base = row.pop().unwrap_or(MathNode::Row(Vec::new()));
+                    row.push(attach_script(base, c == '^', script)?);
+                }
+                '\\' if self.peek_command() == "right" => {
+                    if end != RowEnd::Right {
+                        return Err("unexpected `\\right`".to_string());
+                    }
+                    self.chars.next();
+                    self.read_command_name();
+                    return Ok(row);
+                }
+                _ => row.push(self.parse_atom()?),
+            }
+        }
+    }
+
+    /// Parse a single atom: a symbol, a command with its arguments, or a braced group.
+    fn parse_atom(&mut self) -> Result<MathNode, String> {
+        self.skip_whitespace();
+        let c = self.chars.next().ok_or("missing argument")?;
+        match c {
+            '{' => self.parse_row(RowEnd::Brace).map(MathNode::Row),
+            '\\' => self.parse_command(),
+            '0'..='9' | '.' => {
+                let mut number = c.to_string();
+                while let Some(digit) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(digit);
+                }
+                Ok(symbol(&number, false))
+            }
+            c if c.is_alphabetic() => Ok(symbol(&c.to_string(), true)),
+            '-' => Ok(symbol("−", false)),
+            '\'' => Ok(symbol("′", false)),
+            '*' => Ok(symbol("∗", false)),
+            '&' | '#' | '%' | '~' | '$' | '}' | '^' | '_' => {
+                Err(format!("unsupported character: `{c}`"))
+            }
+            _ => Ok(symbol(&c.to_string(), false)),
+        }
+    }
+
+    fn peek_command(&self) -> String {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.take_while(|c| c.is_ascii_alphabetic()).collect()
+    }
+
+    /// Read the name of a command, after its backslash.
+    fn read_command_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphabetic()) {
+            name.push(c);
+        }
+        if name.is_empty() {
+            if let Some(c) = self.chars.next() {
+                name.push(c);
+            }
+        }
+        name
+    }
+
+    /// Read the raw text of a braced argument.
+    fn read_text_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.chars.next() != Some('{') {
+            return Err("missing text argument".to_string());
+        }
+        let mut text = String::new();
+        for c in self.chars.by_ref() {
+            if c == '}' {
+                return Ok(text);
+            }
+            text.push(c);
+        }
+        Err("missing closing brace".to_string())
+    }
+
+    /// Read a `\left` or `\right` delimiter.
+    fn read_delimiter(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some('.') => Ok(String::new()),
+            Some('\\') => {
+                let name = self.read_command_name();
+                match name.as_str() {
+                    "{" | "}" => Ok(name),
+                    "|" => Ok("‖".to_string()),
+                    _ => command_symbol(&name)
+                        .map(|(text, _)| text.to_string())
+                        .ok_or_else(|| format!("unsupported delimiter: `\\{name}`")),
+                }
+            }
+            Some(c) if "()[]|/<>".contains(c) => Ok(match c {
+                '<' => "⟨".to_string(),
+                '>' => "⟩".to_string(),
+                c => c.to_string(),
+            }),
+            _ => Err("missing delimiter".to_string()),
+        }
+    }
+
+    fn parse_command(&mut self) -> Result<MathNode, String> {
+        let name = self.read_command_name();
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.parse_atom()?;
+                let denominator = self.parse_atom()?;
+                Ok(MathNode::Fraction(
+                    Box::new(numerator),
+                    Box::new(denominator),
+                ))
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.chars.peek() == Some(&'[') {
+                    return Err("roots with an index are not supported".to_string());
+                }
+                Ok(MathNode::Sqrt(Box::new(self.parse_atom()?)))
+            }
+            "text" | "textrm" | "mathrm" | "operatorname" => {
+                Ok(MathNode::Text(self.read_text_argument()?))
+            }
+            "left" => {
+                let open = self.read_delimiter()?;
+                let body = self.parse_row(RowEnd::Right)?;
+                let close = self.read_delimiter()?;
+                Ok(MathNode::Fenced {
+                    open,
+                    body: Box::new(MathNode::Row(body)),
+                    close,
+                })
+            }
+            "," => Ok(MathNode::Space(3.0 / 18.0)),
+            ":" | ">" => Ok(MathNode::Space(4.0 / 18.0)),
+            ";" => Ok(MathNode::Space(5.0 / 18.0)),
+            "!" => Ok(MathNode::Space(-3.0 / 18.0)),
+            " " => Ok(MathNode::Space(0.25)),
+            "quad" => Ok(MathNode::Space(1.0)),
+            "qquad" => Ok(MathNode::Space(2.0)),
+            "{" | "}" | "%" | "$" | "#" | "&" | "_" => Ok(symbol(&name, false)),
+            "|" => Ok(symbol("‖", false)),
+            "sum" | "prod" | "coprod" | "bigcup" | "bigcap" | "bigoplus" | "bigotimes" => {
+                Ok(operator(large_operator(&name), true, true))
+            }
+            "int" | "iint" | "iiint" | "oint" => Ok(operator(large_operator(&name), true, false)),
+            "lim" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" => {
+                Ok(operator(&name, false, true))
+            }
+            "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan"
+            | "sinh" | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "arg" | "deg" | "dim"
+            | "hom" | "ker" => Ok(MathNode::Text(name)),
+            _ => command_symbol(&name)
+                .map(|(text, italic)| symbol(text, italic))
+                .ok_or_else(|| format!("unsupported command: `\\{name}`")),
+        }
+    }
+}
+
+fn symbol(text: &str, italic: bool) -> MathNode {
+    MathNode::Symbol {
+        text: text.to_string(),
+        italic,
+    }
+}
+
+fn operator(text: &str, large: bool, limits: bool) -> MathNode {
+    MathNode::Operator {
+        text: text.to_string(),
+        large,
+        limits,
+    }
+}
+
+/// Attach a superscript or a subscript to a base node.
+fn attach_script(base: MathNode, is_sup: bool, script: MathNode) -> Result<MathNode, String> {
+    let script = Some(Box::new(script));
+    match base {
+        MathNode::Scripts { base, sub, sup } => match (is_sup, &sub, &sup) {
+            (true, _, None) => Ok(MathNode::Scripts {
+                base,
+                sub,
+                sup: script,
+            }),
+            (false, None, _) => Ok(MathNode::Scripts {
+                base,
+                sub: script,
+                sup,
+            }),
+            _ => Err("double superscript or subscript".to_string()),
+        },
+        base => {
+            let (sub, sup) = if is_sup {
+                (None, script)
+            } else {
+                (script, None)
+            };
+            Ok(MathNode::Scripts {
+                base: Box::new(base),
+                sub,
+                sup,
+            })
+        }
+    }
+}
+
+fn large_operator(name: &str) -> &'static str {
+    match name {
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "bigcup" => "⋃",
+        "bigcap" => "⋂",
+        "bigoplus" => "⨁",
+        "bigotimes" => "⨂",
+        "int" => "∫",
+        "iint" => "∬",
+        "iiint" => "∭",
+        _ => "∮",
+    }
+}
+
+/// Symbol of a command, and whether it is drawn in italic.
+fn command_symbol(name: &str) -> Option<(&'static str, bool)> {
+    let lower_greek = match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        _ => "",
+    };
+    if !lower_greek.is_empty() {
+        return Some((lower_greek, true));
+    }
+
+    let upright = match name {
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "times" => "×",
+        "cdot" => "⋅",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "le" | "leq" => "≤",
+        "ge" | "geq" => "≥",
+        "ne" | "neq" => "≠",
+        "ll" => "≪",
+        "gg" => "≫",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "oplus" => "⊕",
+        "otimes" => "⊗",
+        "to" | "rightarrow" => "→",
+        "gets" | "leftarrow" => "←",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "leftrightarrow" => "↔",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "forall" => "∀",
+        "exists" => "∃",
+        "neg" | "lnot" => "¬",
+        "land" | "wedge" => "∧",
+        "lor" | "vee" => "∨",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "circ" => "∘",
+        "ast" => "∗",
+        "star" => "⋆",
+        "prime" => "′",
+        "emptyset" | "varnothing" => "∅",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "vert" | "lvert" | "rvert" | "mid" => "|",
+        "Vert" | "lVert" | "rVert" | "parallel" => "‖",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "perp" => "⊥",
+        "angle" => "∠",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        _ => return None,
+    };
+    Some((upright, false))
+}
+
+/// Inline formulas of the labels being rendered, laid out once their labels are complete.
+#[derive(Debug, Default)]
+pub(crate) struct InlineFormulas(HashMap<gtk::Label, Vec<MathNode>>);
+
+impl InlineFormulas {
+    /// Markup of inline math appended to a label: a placeholder for the formula, or its monospace
+    /// source if it is not supported.
+    ///
+    /// ## Logging
+    ///
+    /// A warning log is emitted if the math is not supported.
+    pub(crate) fn markup(&mut self, label: &gtk::Label, source: &str) -> String {
+        match parse(source) {
+            Ok(node) => {
+                self.0.entry(label.clone()).or_default().push(node);
+                FORMULA_PLACEHOLDER.to_string()
+            }
+            Err(err) => {
+                log::warn!("unable to render inline math `{source}`: {err}");
+                monospace_markup(source)
+            }
+        }
+    }
+
+    /// Lay out the formulas of a complete label with its font size, reserve their space in its
+    /// text, and draw them over it.
+    ///
+    /// The label is replaced in its parent `gtk::Box` by a `gtk::Overlay` drawing the formulas.
+    pub(crate) fn attach(&mut self, label: &gtk::Label) {
+        let Some(formulas) = self.0.remove(label) else {
+            return;
+        };
+        let Some(parent) = label
+            .parent()
+            .and_then(|parent| parent.downcast::<gtk::Box>().ok())
+        else {
+            return;
+        };
+        let cr = match measuring_context() {
+            Ok(cr) => cr,
+            Err(err) => {
+                log::error!("unable to lay out inline math: {err}");
+                return;
+            }
+        };
+
+        // The placeholders take the size of their formula, so that lines make room for them
+        let size = font_size(label);
+        let text = label.text();
+        let attributes = label.attributes().unwrap_or_else(pango::AttrList::new);
+        let mut placed = Vec::new();
+        let indexes = text
+            .match_indices(FORMULA_PLACEHOLDER)
+            .map(|(index, _)| index);
+        for (index, formula) in indexes.zip(&formulas) {
+            let layout = layout_node(formula, &cr, size, false);
+            let rect = pango::Rectangle::new(
+                0,
+                -pango::units_from_double(layout.ascent),
+                pango::units_from_double(layout.width),
+                pango::units_from_double(layout.ascent + layout.descent),
+            );
+            let mut shape = pango::AttrShape::new(&rect, &rect);
+            shape.set_start_index(index as u32);
+            shape.set_end_index((index + FORMULA_PLACEHOLDER.len_utf8()) as u32);
+            attributes.insert(shape);
+            placed.push((index as i32, layout));
+        }
+        label.set_attributes(Some(&attributes));
+
+        let drawing_area = gtk::DrawingArea::builder().can_target(false).build();
+        let weak_label = label.downgrade();
+        drawing_area.set_draw_func(move |area, cr, _, _| {
+            let Some(label) = weak_label.upgrade() else {
+                return;
+            };
+            let Some((label_x, label_y)) = label.translate_coordinates(area, 0.0, 0.0) else {
+                return;
+            };
+            set_text_color(area, cr);
+            let text_layout = label.layout();
+            let (offset_x, offset_y) = label.layout_offsets();
+            for (index, formula) in &placed {
+                let x = pango::units_to_double(text_layout.index_to_pos(*index).x());
+                let (line, _) = text_layout.index_to_line_x(*index, false);
+                let mut lines = text_layout.iter();
+                for _ in 0..line {
+                    lines.next_line();
+                }
+                let baseline = pango::units_to_double(lines.baseline());
+                formula.draw(
+                    cr,
+                    label_x + offset_x as f64 + x,
+                    label_y + offset_y as f64 + baseline,
+                );
+            }
+        });
+        let overlay = gtk::Overlay::new();
+        parent.insert_child_after(&overlay, Some(label));
+        parent.remove(label);
+        overlay.set_child(Some(label));
+        overlay.add_overlay(&drawing_area);
+    }
+}
+
+/// Font size of the text of a label, in pixels.
+fn font_size(label: &gtk::Label) -> f64 {
+    let Some(font) = label.pango_context().font_description() else {
+        return INLINE_FONT_SIZE;
+    };
+    let size = pango::units_to_double(font.size());
+    if font.is_size_absolute() {
+        size
+    } else {
+        // Points at the default resolution of 96 DPI
+        size * 96.0 / 72.0
+    }
+}
+
+/// Monospace markup of math that cannot be rendered.
+fn monospace_markup(source: &str) -> String {
+    format!("<tt>{}</tt>", html_escape(source))
+}
+
+/// Cairo context to measure text with before drawing it.
+fn measuring_context() -> Result<cairo::Context, String> {
+    let surface =
+        cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).map_err(|err| err.to_string())?;
+    cairo::Context::new(&surface).map_err(|err| err.to_string())
+}
+
+/// Draw with the text color of a widget.
+fn set_text_color(widget: &impl IsA<gtk::Widget>, cr: &cairo::Context) {
+    let color = widget.style_context().color();
+    cr.set_source_rgba(
+        color.red() as f64,
+        color.green() as f64,
+        color.blue() as f64,
+        color.alpha() as f64,
+    );
+}
+
+/// Create a widget displaying block math, or its monospace source if it is not supported.
+pub(crate) fn block_widget(source: &str) -> gtk::Widget {
+    let layout = parse(source).and_then(|node| {
+        let cr = measuring_context()?;
+        Ok(layout_node(&node, &cr, BLOCK_FONT_SIZE, true))
+    });
+
+    match layout {
+        Ok(layout) => {
+            let drawing_area = gtk::DrawingArea::builder()
+                .content_width((layout.width + 2.0 * BLOCK_PADDING).ceil() as i32)
+                .content_height(
+                    (layout.ascent + layout.descent + 2.0 * BLOCK_PADDING).ceil() as i32,
+                )
+                .halign(gtk::Align::Center)
+                .css_classes(vec!["commonmark_math_block"])
+                .build();
+            drawing_area.set_draw_func(move |area, cr, _, _| {
+                set_text_color(area, cr);
+                layout.draw(cr, BLOCK_PADDING, BLOCK_PADDING + layout.ascent);
+            });
+            drawing_area.upcast()
+        }
+        Err(err) => {
+            log::warn!("unable to render math block: {err}");
+            gtk::Label::builder()
+                .label(monospace_markup(source))
+                .use_markup(true)
+                .selectable(true)
+                .wrap(true)
+                .halign(gtk::Align::Center)
+                .css_classes(vec!["commonmark_math_block"])
+                .build()
+                .upcast()
+        }
+    }
+}
+
+/// Something to draw, positioned relatively to the baseline origin of its layout.
+#[derive(Debug)]
+enum Item {
+    Glyph {
+        x: f64,
+        y: f64,
+        text: String,
+        size: f64,
+        italic: bool,
+    },
+    /// A filled rectangle, such as a fraction bar
+    Rule {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    /// A stroked polyline, such as a radical sign
+    Path { points: Vec<(f64, f64)>, width: f64 },
+}
+
+/// Laid out math, with its dimensions around the baseline.
+#[derive(Debug, Default)]
+struct Layout {
+    width: f64,
+    ascent: f64,
+    descent: f64,
+    items: Vec<Item>,
+}
+
+impl Layout {
+    /// Append another layout at the given offset from this layout origin.
+    fn append(&mut self, other: Layout, dx: f64, dy: f64) {
+        for item in other.items {
+            self.items.push(match item {
+                Item::Glyph {
+                    x,
+                    y,
+                    text,
+                    size,
+                    italic,
+                } => Item::Glyph {
+                    x: x + dx,
+                    y: y + dy,
+                    text,
+                    size,
+                    italic,
+                },
+                Item::Rule {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => Item::Rule {
+                    x: x + dx,
+                    y: y + dy,
+                    width,
+                    height,
+                },
+                Item::Path { points, width } => Item::Path {
+                    points: points.into_iter().map(|(x, y)| (x + dx, y + dy)).collect(),
+                    width,
+                },
+            });
+        }
+    }
+
+    fn draw(&self, cr: &cairo::Context, origin_x: f64, origin_y: f64) {
+        for item in &self.items {
+            let result = match item {
+                Item::Glyph {
+                    x,
+                    y,
+                    text,
+                    size,
+                    italic,
+                } => {
+                    select_font(cr, *size, *italic);
+                    cr.move_to(origin_x + x, origin_y + y);
+                    cr.show_text(text)
+                }
+                Item::Rule {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => {
+                    cr.rectangle(origin_x + x, origin_y + y, *width, *height);
+                    cr.fill()
+                }
+                Item::Path { points, width } => {
+                    for (index, (x, y)) in points.iter().enumerate() {
+                        if index == 0 {
+                            cr.move_to(origin_x + x, origin_y + y);
+                        } else {
+                            cr.line_to(origin_x + x, origin_y + y);
+                        }
+                    }
+                    cr.set_line_width(*width);
+                    cr.stroke()
+                }
+            };
+            if let Err(err) = result {
+                log::error!("unable to draw math: {err}");
+            }
+        }
+    }
+}
+
+fn select_font(cr: &cairo::Context, size: f64, italic: bool) {
+    let slant = if italic {
+        cairo::FontSlant::Italic
+    } else {
+        cairo::FontSlant::Normal
+    };
+    cr.select_font_face(MATH_FONT_FAMILY, slant, cairo::FontWeight::Normal);
+    cr.set_font_size(size);
+}
+
+fn layout_glyph(cr: &cairo::Context, text: &str, size: f64, italic: bool) -> Layout {
+    select_font(cr, size, italic);
+    let width = cr
+        .text_extents(text)
+        .map(|extents| extents.x_advance())
+        .unwrap_or(size * 0.5 * text.chars().count() as f64);
+    let (ascent, descent) = cr
+        .font_extents()
+        .map(|extents| (extents.ascent(), extents.descent()))
+        .unwrap_or((size * 0.8, size * 0.2));
+    Layout {
+        width,
+        ascent,
+        descent,
+        items: vec![Item::Glyph {
+            x: 0.0,
+            y: 0.0,
+            text: text.to_string(),
+            size,
+            italic,
+        }],
+    }
+}
+
+/// Lay out a node with the given font size. Display style draws large operators bigger, with their
+/// scripts as limits.
+fn layout_node(node: &MathNode, cr: &cairo::Context, size: f64, display: bool) -> Layout {
+    let script_size = (size * 0.7).max(8.0);
+    match node {
+        MathNode::Symbol { text, italic } => layout_glyph(cr, text, size, *italic),
+        MathNode::Text(text) => layout_glyph(cr, text, size, false),
+        MathNode::Operator { text, large, .. } => {
+            let size = if *large && display { size * 1.5 } else { size };
+            layout_glyph(cr, text, size, false)
+        }
+        MathNode::Space(em) => Layout {
+            width: em * size,
+            ..Default::default()
+        },
+        MathNode::Row(children) => {
+            let mut layout = Layout::default();
+            for child in children {
+                let child = layout_node(child, cr, size, display);
+                let x = layout.width;
+                layout.width += child.width;
+                layout.ascent = layout.ascent.max(child.ascent);
+                layout.descent = layout.descent.max(child.descent);
+                layout.append(child, x, 0.0);
+            }
+            layout
+        }
+        MathNode::Scripts { base, sub, sup } => {
+            let with_limits =
+                display && matches!(base.as_ref(), MathNode::Operator { limits: true, .. });
+            let base = layout_node(base, cr, size, display);
+            let sub = sub
+                .as_ref()
+                .map(|sub| layout_node(sub, cr, script_size, false));
+            let sup = sup
+                .as_ref()
+                .map(|sup| layout_node(sup, cr, script_size, false));
+
+            let mut layout = Layout::default();
+            if with_limits {
+                let gap = size * 0.1;
+                let width = [
+                    base.width,
+                    sub.as_ref().map_or(0.0, |l| l.width),
+                    sup.as_ref().map_or(0.0, |l| l.width),
+                ]
+                .into_iter()
+                .fold(0.0, f64::max);
+                layout.width = width;
+                layout.ascent = base.ascent;
+                layout.descent = base.descent;
+                if let Some(sup) = sup {
+                    let y = -(base.ascent + gap + sup.descent);
+                    layout.ascent = base.ascent + gap + sup.descent + sup.ascent;
+                    layout.append(centered(sup, width), 0.0, y);
+                }
+                if let Some(sub) = sub {
+                    let y = base.descent + gap + sub.ascent;
+                    layout.descent = base.descent + gap + sub.ascent + sub.descent;
+                    layout.append(centered(sub, width), 0.0, y);
+                }
+                layout.append(centered(base, width), 0.0, 0.0);
+            } else {
+                let x = base.width;
+                layout.width = base.width;
+                layout.ascent = base.ascent;
+                layout.descent = base.descent;
+                layout.append(base, 0.0, 0.0);
+                if let Some(sup) = sup {
+                    let shift = size * 0.45;
+                    layout.width = layout.width.max(x + sup.width);
+                    layout.ascent = layout.ascent.max(shift + sup.ascent);
+                    layout.append(sup, x, -shift);
+                }
+                if let Some(sub) = sub {
+                    let shift = size * 0.25;
+                    layout.width = layout.width.max(x + sub.width);
+                    layout.descent = layout.descent.max(shift + sub.descent);
+                    layout.append(sub, x, shift);
+                }
+            }
+            layout
+        }
+        MathNode::Fraction(numerator, denominator) => {
+            let term_size = if display { size } else { script_size };
+            let numerator = layout_node(numerator, cr, term_size, false);
+            let denominator = layout_node(denominator, cr, term_size, false);
+            let axis = size * 0.25;
+            let thickness = (size * 0.05).max(1.0);
+            let gap = size * 0.15;
+            let padding = size * 0.1;
+            let width = numerator.width.max(denominator.width) + 2.0 * padding;
+
+            let numerator_y = -(axis + thickness / 2.0 + gap + numerator.descent);
+            let denominator_y = -axis + thickness / 2.0 + gap + denominator.ascent;
+            let mut layout = Layout {
+                width,
+                ascent: -numerator_y + numerator.ascent,
+                descent: denominator_y + denominator.descent,
+                items: vec![Item::Rule {
+                    x: 0.0,
+                    y: -axis - thickness / 2.0,
+                    width,
+                    height: thickness,
+                }],
+            };
+            layout.append(centered(numerator, width), 0.0, numerator_y);
+            layout.append(centered(denominator, width), 0.0, denominator_y);
+            layout
+        }
+        MathNode::Sqrt(body) => {
+            let body = layout_node(body, cr, size, display);
+            let thickness = (size * 0.05).max(1.0);
+            let gap = size * 0.1;
+            let sign_width = size * 0.6;
+            let top = -(body.ascent + gap + thickness / 2.0);
+            let bottom = body.descent;
+            let width = sign_width + body.width + size * 0.1;
+            let mut layout = Layout {
+                width,
+                ascent: body.ascent + gap + thickness,
+                descent: body.descent,
+                items: vec![Item::Path {
+                    points: vec![
+                        (0.0, bottom - (bottom - top) * 0.4),
+                        (sign_width * 0.3, bottom - (bottom - top) * 0.5),
+                        (sign_width * 0.6, bottom),
+                        (sign_width, top),
+                        (width, top),
+                    ],
+                    width: thickness,
+                }],
+            };
+            layout.append(body, sign_width, 0.0);
+            layout
+        }
+        MathNode::Fenced { open, body, close } => {
+            let body = layout_node(body, cr, size, display);
+            let delimiter_size = size.max((body.ascent + body.descent) * 1.1);
+            let center = (body.ascent - body.descent) / 2.0;
+            let mut layout = Layout {
+                ascent: body.ascent,
+                descent: body.descent,
+                ..Default::default()
+            };
+            append_delimiter(&mut layout, cr, open, delimiter_size, center);
+            let x = layout.width;
+            layout.width += body.width;
+            layout.append(body, x, 0.0);
+            append_delimiter(&mut layout, cr, close, delimiter_size, center);
+            layout
+        }
+    }
+}
+
+/// Append a delimiter to a layout, vertically centered on the given height above the baseline.
+fn append_delimiter(
+    layout: &mut Layout,
+    cr: &cairo::Context,
+    delimiter: &str,
+    size: f64,
+    center: f64,
+) {
+    if delimiter.is_empty() {
+        return;
+    }
+    let glyph = layout_glyph(cr, delimiter, size, false);
+    let dy = -center + (glyph.ascent - glyph.descent) / 2.0;
+    let x = layout.width;
+    layout.ascent = layout.ascent.max(glyph.ascent - dy);
+    layout.descent = layout.descent.max(glyph.descent + dy);
+    layout.width += glyph.width;
+    layout.append(glyph, x, dy);
+}
+
+/// Offset a layout so that it is horizontally centered in the given width.
+fn centered(layout: Layout, width: f64) -> Layout {
+    let dx = (width - layout.width) / 2.0;
+    let mut centered = Layout {
+        width,
+        ascent: layout.ascent,
+        descent: layout.descent,
+        items: Vec::new(),
+    };
+    centered.append(layout, dx, 0.0);
+    centered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: f64 = 20.0;
+
+    fn inline_layout(source: &str) -> Layout {
+        let node = parse(source).unwrap();
+        layout_node(&node, &measuring_context().unwrap(), SIZE, false)
+    }
+
+    /// Position and font size of the first glyph with the given text.
+    fn glyph(layout: &Layout, glyph_text: &str) -> (f64, f64, f64) {
+        layout
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Glyph {
+                    x, y, text, size, ..
+                } if text == glyph_text => Some((*x, *y, *size)),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn fractions() {
+        let MathNode::Row(row) = parse(r"\frac{a}{b+1}").unwrap() else {
+            panic!("math is not parsed as a row");
+        };
+        assert!(matches!(row.as_slice(), [MathNode::Fraction(..)]));
+
+        let fraction = inline_layout(r"\frac{a}{b}");
+        let symbol = inline_layout("a");
+        assert!(fraction.ascent > symbol.ascent);
+        assert!(fraction.descent > symbol.descent);
+        assert!(fraction
+            .items
+            .iter()
+            .any(|item| matches!(item, Item::Rule { width, .. } if *width == fraction.width)));
+        // The numerator is above the baseline and the denominator below it, both smaller in
+        // inline math
+        let (_, numerator_y, numerator_size) = glyph(&fraction, "a");
+        let (_, denominator_y, denominator_size) = glyph(&fraction, "b");
+        assert!(numerator_y < 0.0 && denominator_y > 0.0);
+        assert!(numerator_size < SIZE && denominator_size < SIZE);
+
+        assert!(parse(r"\frac{a}").is_err());
+    }
+
+    #[test]
+    fn scripts() {
+        let MathNode::Row(row) = parse("x^2_i").unwrap() else {
+            panic!("math is not parsed as a row");
+        };
+        assert!(matches!(
+            row.as_slice(),
+            [MathNode::Scripts {
+                sub: Some(_),
+                sup: Some(_),
+                ..
+            }]
+        ));
+
+        let scripts = inline_layout("x^2_i");
+        let base = inline_layout("x");
+        let (sup_x, sup_y, sup_size) = glyph(&scripts, "2");
+        let (sub_x, sub_y, sub_size) = glyph(&scripts, "i");
+        assert_eq!(sup_x, base.width);
+        assert_eq!(sub_x, base.width);
+        assert!(sup_y < 0.0 && sub_y > 0.0);
+        assert!(sup_size < SIZE && sub_size < SIZE);
+
+        assert_eq!(
+            parse("x^2^3").unwrap_err(),
+            "double superscript or subscript"
+        );
+    }
+
+    #[test]
+    fn left_right_delimiters() {
+        let MathNode::Row(row) = parse(r"\left( \frac{a}{b} \right]").unwrap() else {
+            panic!("math is not parsed as a row");
+        };
+        assert!(matches!(
+            row.as_slice(),
+            [MathNode::Fenced { open, close, .. }] if open == "(" && close == "]"
+        ));
+
+        // Delimiters are stretched to the height of their content
+        let fenced = inline_layout(r"\left( \frac{a}{b} \right]");
+        let fraction = inline_layout(r"\frac{a}{b}");
+        let (open_x, _, open_size) = glyph(&fenced, "(");
+        let (close_x, _, close_size) = glyph(&fenced, "]");
+        assert_eq!(open_x, 0.0);
+        assert!(close_x >= fraction.width);
+        assert!(open_size > SIZE && close_size == open_size);
+
+        // `.` is an invisible delimiter
+        let MathNode::Row(row) = parse(r"\left. x \right|").unwrap() else {
+            panic!("math is not parsed as a row");
+        };
+        assert!(matches!(
+            row.as_slice(),
+            [MathNode::Fenced { open, close, .. }] if open.is_empty() && close == "|"
+        ));
+
+        assert_eq!(parse(r"\left( x").unwrap_err(), "missing `\\right`");
+        assert_eq!(parse(r"x \right)").unwrap_err(), "unexpected `\\right`");
+    }
+
+    #[test]
+    fn unsupported_commands_fall_back_to_monospace() {
+        assert_eq!(
+            parse(r"\unknown{x}").unwrap_err(),
+            "unsupported command: `\\unknown`"
+        );
+        assert!(parse(r"\sqrt[3]{x}").is_err());
+        assert_eq!(
+            monospace_markup(r"\unknown{a<b}"),
+            r"<tt>\unknown{a&lt;b}</tt>"
+        );
+    }
+}
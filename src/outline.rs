@@ -0,0 +1,428 @@
+//! Outline of the document headings, as list models and as a sidebar.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use gtk::{gio, glib, pango, prelude::*, subclass::prelude::*};
+
+use crate::anchors::scroll_to_widget;
+
+mod imp {
+    use std::cell::{Cell, RefCell};
+
+    use gtk::{gio, glib, glib::once_cell::sync::Lazy, prelude::*, subclass::prelude::*};
+
+    pub struct OutlineItem {
+        pub(super) level: Cell<u8>,
+        pub(super) text: RefCell<String>,
+        pub(super) slug: RefCell<String>,
+        pub(super) widget: glib::WeakRef<gtk::Widget>,
+        pub(super) children: gio::ListStore,
+    }
+
+    impl Default for OutlineItem {
+        fn default() -> Self {
+            Self {
+                level: Cell::default(),
+                text: RefCell::default(),
+                slug: RefCell::default(),
+                widget: glib::WeakRef::new(),
+                children: gio::ListStore::new(super::OutlineItem::static_type()),
+            }
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for OutlineItem {
+        const NAME: &'static str = "CommonmarkOutlineItem";
+        type Type = super::OutlineItem;
+    }
+
+    impl ObjectImpl for OutlineItem {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+                vec![
+                    glib::ParamSpecUInt::builder("level")
+                        .minimum(1)
+                        .maximum(6)
+                        .default_value(1)
+                        .read_only()
+                        .build(),
+                    glib::ParamSpecString::builder("text").read_only().build(),
+                    glib::ParamSpecString::builder("slug").read_only().build(),
+                    glib::ParamSpecObject::builder::<gtk::Widget>("widget")
+                        .read_only()
+                        .build(),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            match pspec.name() {
+                "level" => u32::from(self.level.get()).to_value(),
+                "text" => self.text.borrow().to_value(),
+                "slug" => self.slug.borrow().to_value(),
+                "widget" => self.widget.upgrade().to_value(),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+glib::wrapper! {
+    /// A heading of the outline, with the `level`, `text`, `slug` and `widget` read-only
+    /// properties.
+    pub struct OutlineItem(ObjectSubclass<imp::OutlineItem>);
+}
+
+impl OutlineItem {
+    fn new(level: u8, text: &str, slug: &str, widget: &impl IsA<gtk::Widget>) -> Self {
+        let item: Self = glib::Object::builder().build();
+        let imp = item.imp();
+        imp.level.set(level);
+        imp.text.replace(text.to_string());
+        imp.slug.replace(slug.to_string());
+        imp.widget.set(Some(widget.upcast_ref()));
+        item
+    }
+
+    /// Level of the heading, from 1 to 6.
+    pub fn level(&self) -> u8 {
+        self.imp().level.get()
+    }
+
+    /// Plain text of the heading.
+    pub fn text(&self) -> String {
+        self.imp().text.borrow().clone()
+    }
+
+    /// Slug of the heading, which `#anchor` links and
+    /// [`RenderedDocument::scroll_to_anchor`](crate::RenderedDocument::scroll_to_anchor) use.
+    pub fn slug(&self) -> String {
+        self.imp().slug.borrow().clone()
+    }
+
+    /// Widget of the heading, if it is still alive.
+    pub fn widget(&self) -> Option<gtk::Widget> {
+        self.imp().widget.upgrade()
+    }
+
+    /// Headings nested under this one, as a list model of [`OutlineItem`].
+    pub fn children(&self) -> gio::ListModel {
+        self.imp().children.clone().upcast()
+    }
+
+    /// Whether two headings have the same properties and the same nested headings.
+    fn same_as(&self, other: &OutlineItem) -> bool {
+        let children = model_items(&self.children());
+        let other_children = model_items(&other.children());
+        self.level() == other.level()
+            && self.text() == other.text()
+            && self.slug() == other.slug()
+            && self.widget() == other.widget()
+            && children.len() == other_children.len()
+            && children
+                .iter()
+                .zip(&other_children)
+                .all(|(child, other_child)| child.same_as(other_child))
+    }
+}
+
+/// Outline of a document, made of its headings nested by level.
+#[derive(Debug, Clone)]
+pub struct Outline {
+    /// Top-level headings
+    root: gio::ListStore,
+    /// Headings in document order
+    items: Vec<OutlineItem>,
+    /// Headings that are not nested yet, from the last top-level one to the last one
+    stack: Vec<OutlineItem>,
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Self {
+            root: gio::ListStore::new(OutlineItem::static_type()),
+            items: Vec::new(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl Outline {
+    /// Add a heading, nested under the last heading of a lower level.
+    pub(crate) fn push(
+        &mut self,
+        level: u8,
+        text: &str,
+        slug: &str,
+        widget: &impl IsA<gtk::Widget>,
+    ) {
+        let item = OutlineItem::new(level, text, slug, widget);
+        while self
+            .stack
+            .last()
+            .is_some_and(|parent| parent.level() >= level)
+        {
+            self.stack.pop();
+        }
+        match self.stack.last() {
+            Some(parent) => parent.imp().children.append(&item),
+            None => self.root.append(&item),
+        }
+        self.stack.push(item.clone());
+        self.items.push(item);
+    }
+
+    /// Replace the headings with the ones of another outline, updating the list model in place so
+    /// that the models and sidebars created from this outline follow the change.
+    ///
+    /// The leading headings that did not change are kept, so that their rows are not rebuilt.
+    pub(crate) fn replace(&mut self, outline: Outline) {
+        let current = model_items(self.root.upcast_ref());
+        let new = model_items(outline.root.upcast_ref());
+        let kept = current
+            .iter()
+            .zip(&new)
+            .take_while(|(current, new)| current.same_as(new))
+            .count();
+        let additions: Vec<glib::Object> = new[kept..]
+            .iter()
+            .map(|item| item.clone().upcast())
+            .collect();
+        self.root
+            .splice(kept as u32, (current.len() - kept) as u32, &additions);
+
+        self.items = flatten(self.root.upcast_ref());
+        // Last heading of each level, as if the headings had been pushed to this outline
+        self.stack.clear();
+        let mut last = model_items(self.root.upcast_ref()).pop();
+        while let Some(item) = last {
+            last = model_items(&item.children()).pop();
+            self.stack.push(item);
+        }
+    }
+
+    /// Copy of the outline made of new items, so that the headings pushed to the copy do not
+    /// change this outline.
+    pub(crate) fn duplicate(&self) -> Outline {
+        let mut outline = Outline::default();
+        for item in &self.items {
+            if let Some(widget) = item.widget() {
+                outline.push(item.level(), &item.text(), &item.slug(), &widget);
+            }
+        }
+        outline
+    }
+
+    /// Headings in document order.
+    pub fn items(&self) -> &[OutlineItem] {
+        &self.items
+    }
+
+    /// Top-level headings, as a list model of [`OutlineItem`]. Nested headings are available from
+    /// [`OutlineItem::children`].
+    pub fn model(&self) -> gio::ListModel {
+        self.root.clone().upcast()
+    }
+
+    /// Heading tree, as a tree list model of `gtk::TreeListRow` whose items are
+    /// [`OutlineItem`]. Rows are expanded by default.
+    pub fn tree_model(&self) -> gtk::TreeListModel {
+        gtk::TreeListModel::new(&self.root, false, true, |item| {
+            let children = item.downcast_ref::<OutlineItem>()?.children();
+            (children.n_items() > 0).then_some(children)
+        })
+    }
+
+    /// Create a sidebar listing the headings of the document rendered in the viewport.
+    ///
+    /// Clicking a heading scrolls the document to it, and the heading of the section in view is
+    /// selected as the document is scrolled. The viewport must be inside a `gtk::ScrolledWindow`.
+    pub(crate) fn sidebar(&self, viewport: &gtk::Viewport) -> gtk::ScrolledWindow {
+        let tree_model = self.tree_model();
+        let selection = gtk::SingleSelection::new(Some(tree_model.clone()));
+        selection.set_autoselect(false);
+        selection.set_can_unselect(true);
+        selection.set_selected(gtk::INVALID_LIST_POSITION);
+
+        let factory = gtk::SignalListItemFactory::new();
+        factory.connect_setup(|_, list_item| {
+            let expander = gtk::TreeExpander::new();
+            expander.set_child(Some(
+                &gtk::Label::builder()
+                    .xalign(0.0)
+                    .ellipsize(pango::EllipsizeMode::End)
+                    .build(),
+            ));
+            list_item.set_child(Some(&expander));
+        });
+        factory.connect_bind(|_, list_item| {
+            let Some(row) = list_item
+                .item()
+                .and_then(|item| item.downcast::<gtk::TreeListRow>().ok())
+            else {
+                return;
+            };
+            let Some(expander) = list_item
+                .child()
+                .and_then(|child| child.downcast::<gtk::TreeExpander>().ok())
+            else {
+                return;
+            };
+            let text = row
+                .item()
+                .and_then(|item| item.downcast::<OutlineItem>().ok())
+                .map(|item| item.text())
+                .unwrap_or_default();
+            if let Some(label) = expander
+                .child()
+                .and_then(|child| child.downcast::<gtk::Label>().ok())
+            {
+                label.set_label(&text);
+                label.set_tooltip_text(Some(&text));
+            }
+            expander.set_list_row(Some(&row));
+        });
+
+        // Selecting the section in view must not scroll to its heading
+        let tracking = Rc::new(Cell::new(false));
+        let selection_tracking = tracking.clone();
+        selection.connect_selection_changed(move |selection, _, _| {
+            if selection_tracking.get() {
+                return;
+            }
+            if let Some(widget) = selection
+                .selected_item()
+                .and_then(|row| row.downcast::<gtk::TreeListRow>().ok())
+                .and_then(|row| row.item())
+                .and_then(|item| item.downcast::<OutlineItem>().ok())
+                .and_then(|item| item.widget())
+            {
+                scroll_to_widget(&widget);
+            }
+        });
+
+        let list_view = gtk::ListView::builder()
+            .model(&selection)
+            .factory(&factory)
+            .css_classes(vec!["navigation-sidebar"])
+            .build();
+
+        // Rows are read from the model, which is updated along with the document, and computed
+        // again when headings change or are expanded or collapsed
+        let rows: Rc<RefCell<Option<Rows>>> = Rc::default();
+        let changed_rows = rows.clone();
+        selection.connect_items_changed(move |_, _, _, _| {
+            changed_rows.take();
+        });
+
+        // The viewport gets its adjustment once it is put in a scrolled window
+        let root = self.root.clone();
+        let track = move |viewport: &gtk::Viewport| {
+            let Some(adjustment) = viewport.vadjustment() else {
+                return;
+            };
+            let root = root.clone();
+            let viewport = viewport.downgrade();
+            let selection = selection.clone();
+            let tracking = tracking.clone();
+            let rows = rows.clone();
+            adjustment.connect_value_changed(move |adjustment| {
+                let Some(content) = viewport.upgrade().and_then(|viewport| viewport.child()) else {
+                    return;
+                };
+                let position = rows
+                    .borrow_mut()
+                    .get_or_insert_with(|| Rows::new(root.upcast_ref(), &selection))
+                    .current_position(&content, adjustment.value());
+                if position != selection.selected() {
+                    tracking.set(true);
+                    selection.set_selected(position);
+                    tracking.set(false);
+                }
+            });
+        };
+        track(viewport);
+        viewport.connect_vadjustment_notify(track);
+
+        gtk::ScrolledWindow::builder()
+            .name("commonmark_outline_sidebar")
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .child(&list_view)
+            .build()
+    }
+}
+
+/// Headings of a list model of [`OutlineItem`], without their nested headings.
+fn model_items(model: &gio::ListModel) -> Vec<OutlineItem> {
+    (0..model.n_items())
+        .filter_map(|position| model.item(position)?.downcast::<OutlineItem>().ok())
+        .collect()
+}
+
+/// Headings of a list model of [`OutlineItem`] and their nested headings, in document order.
+fn flatten(model: &gio::ListModel) -> Vec<OutlineItem> {
+    let mut items = Vec::new();
+    for item in model_items(model) {
+        let children = item.children();
+        items.push(item);
+        items.extend(flatten(&children));
+    }
+    items
+}
+
+/// Rows of an outline sidebar, matched with the headings of the outline.
+struct Rows {
+    /// Headings in document order
+    items: Vec<OutlineItem>,
+    /// Position of the row of each heading, or of its closest visible ancestor if it is collapsed
+    positions: Vec<u32>,
+}
+
+impl Rows {
+    fn new(root: &gio::ListModel, selection: &gtk::SingleSelection) -> Rows {
+        let items = flatten(root);
+        let indexes: HashMap<&OutlineItem, usize> = items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (item, index))
+            .collect();
+        let mut positions = vec![gtk::INVALID_LIST_POSITION; items.len()];
+        for row_position in 0..selection.n_items() {
+            if let Some(&index) = selection
+                .item(row_position)
+                .and_then(|row| row.downcast::<gtk::TreeListRow>().ok())
+                .and_then(|row| row.item())
+                .and_then(|item| item.downcast::<OutlineItem>().ok())
+                .and_then(|item| indexes.get(&item))
+            {
+                positions[index] = row_position;
+            }
+        }
+        // Rows are in document order, so hidden headings are shown by the previous visible row
+        for index in 1..positions.len() {
+            if positions[index] == gtk::INVALID_LIST_POSITION {
+                positions[index] = positions[index - 1];
+            }
+        }
+        Rows { items, positions }
+    }
+
+    /// Position of the row of the section in view, which is the last heading above the top of
+    /// the view, or its closest visible ancestor if it is collapsed.
+    fn current_position(&self, content: &gtk::Widget, scroll: f64) -> u32 {
+        self.items
+            .iter()
+            .rposition(|item| {
+                item.widget()
+                    .and_then(|widget| widget.translate_coordinates(content, 0.0, 0.0))
+                    .is_some_and(|(_, y)| y <= scroll + 1.0)
+            })
+            .map_or(gtk::INVALID_LIST_POSITION, |index| self.positions[index])
+    }
+}
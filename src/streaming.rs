@@ -0,0 +1,455 @@
+//! Rendering of markdown received in chunks, with the constructs left open by the last chunk
+//! rendered as if they were closed.
+
+use crate::{anchors::Slugger, html, render_document, Outline, RenderConfig, RenderedDocument};
+
+/// Renderer of markdown received in chunks, such as a chat message received token by token.
+///
+/// The input is split into a finalized prefix, made of the blocks followed by a blank line, and the
+/// trailing blocks that the next chunks can still change. The blocks of the prefix are rendered
+/// once, and only the trailing blocks are rendered again for each chunk, so that rendering a
+/// message takes linear time. Unterminated code fences, emphasis, code spans and tables of the
+/// trailing blocks are rendered as if they were closed.
+///
+/// Link definitions and footnotes only apply to the chunks finalized along with them, until
+/// [`StreamingRenderer::finish`] renders the whole input.
+///
+/// ## Example
+///
+/// ```rust,ignore
+/// let mut stream = StreamingRenderer::new(RenderConfig::default())?;
+/// scrollable.set_child(Some(stream.document().viewport()));
+/// stream.push_str("Some **bold")?;
+/// stream.push_str(" text**")?;
+/// stream.finish()?;
+/// ```
+#[derive(Debug)]
+pub struct StreamingRenderer<'a> {
+    render_config: RenderConfig<'a>,
+    input: String,
+    /// End of the finalized prefix of the input, whose blocks are not rendered again
+    finalized: usize,
+    /// Number of blocks of the document rendered from the finalized prefix
+    finalized_blocks: usize,
+    /// Slugs of the headings of the finalized prefix
+    slugger: Slugger,
+    /// Headings of the finalized prefix
+    outline: Outline,
+    document: RenderedDocument,
+}
+
+impl<'a> StreamingRenderer<'a> {
+    /// Create a renderer with an empty document.
+    ///
+    /// ## Errors
+    ///
+    /// See [`render_document`](crate::render_document).
+    pub fn new(render_config: RenderConfig<'a>) -> anyhow::Result<Self> {
+        let document = render_document("", &render_config)?;
+        Ok(Self {
+            render_config,
+            input: String::new(),
+            finalized: 0,
+            finalized_blocks: 0,
+            slugger: Slugger::default(),
+            outline: Outline::default(),
+            document,
+        })
+    }
+
+    /// Append a chunk to the input and update the document, rendering the blocks finalized by the
+    /// chunk and the trailing blocks.
+    ///
+    /// ## Errors
+    ///
+    /// See [`render_document`](crate::render_document). The chunk is appended even if the
+    /// document cannot be updated.
+    pub fn push_str(&mut self, chunk: &str) -> anyhow::Result<()> {
+        self.input.push_str(chunk);
+        self.document.truncate_blocks(self.finalized_blocks);
+
+        let front_matter = self.render_config.parse_options.constructs.frontmatter;
+        let finalized = finalized_end(&self.input, self.finalized, front_matter);
+        if finalized > self.finalized {
+            self.document.append_part(
+                &self.input[self.finalized..finalized],
+                self.finalized == 0,
+                &self.render_config,
+                &mut self.slugger,
+                &mut self.outline,
+            )?;
+            self.finalized = finalized;
+            self.finalized_blocks = self.document.block_count();
+        }
+
+        let tail = close_open_constructs(&self.input[self.finalized..]);
+        let mut slugger = self.slugger.clone();
+        let mut outline = self.outline.duplicate();
+        self.document.append_part(
+            &tail,
+            self.finalized == 0,
+            &self.render_config,
+            &mut slugger,
+            &mut outline,
+        )?;
+        self.document.set_outline(outline);
+        Ok(())
+    }
+
+    /// Update the document with the whole input as it is, once it is complete.
+    ///
+    /// The widgets of the finalized blocks are reused, unless the document has front matter, link
+    /// definitions or footnotes.
+    ///
+    /// ## Errors
+    ///
+    /// See [`render_document`](crate::render_document).
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        self.document.update(&self.input, &self.render_config)
+    }
+
+    /// Input received so far.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Rendered document.
+    pub fn document(&self) -> &RenderedDocument {
+        &self.document
+    }
+}
+
+/// End of the finalized prefix of the input, from the end of the previous one: the start of the
+/// last line starting a top-level block after a blank line.
+///
+/// Lines inside code fences, front matter if it is enabled, or a block HTML element such as
+/// `<details>` cannot end the prefix, nor can the last line until it is complete.
+fn finalized_end(input: &str, start: usize, front_matter: bool) -> usize {
+    let mut end = start;
+    let mut offset = start;
+    let mut open_fence: Option<String> = None;
+    let mut open_html: Vec<String> = Vec::new();
+    let mut after_blank = false;
+    for line in input[start..].split_inclusive('\n') {
+        if !line.ends_with('\n') {
+            break;
+        }
+        let trimmed = line.trim();
+        match &open_fence {
+            Some(fence) => {
+                if closes_fence(trimmed, fence) {
+                    open_fence = None;
+                }
+            }
+            // Front matter is closed like a fence
+            None if front_matter && offset == 0 && matches!(trimmed, "---" | "+++") => {
+                open_fence = Some(trimmed.to_string());
+            }
+            None => {
+                if after_blank && open_html.is_empty() && starts_top_level_block(line) {
+                    end = offset;
+                }
+                if let Some(fence) = fence_opening(trimmed) {
+                    open_fence = Some(fence);
+                } else if trimmed.starts_with('<') {
+                    html::track_open_blocks(trimmed, &mut open_html);
+                }
+            }
+        }
+        after_blank = open_fence.is_none() && trimmed.is_empty();
+        offset += line.len();
+    }
+    end
+}
+
+/// Whether a line following a blank line starts a top-level block, rather than continuing the
+/// list or the indented content before the blank line.
+fn starts_top_level_block(line: &str) -> bool {
+    if line.starts_with(char::is_whitespace) {
+        return false;
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let after_marker = match digits {
+        0 => line.strip_prefix(['-', '*', '+']),
+        _ => line[digits..].strip_prefix(['.', ')']),
+    };
+    let is_list_item = after_marker.is_some_and(|rest| rest.starts_with(char::is_whitespace));
+    !is_list_item
+}
+
+/// Whether a line closes a code fence.
+fn closes_fence(line: &str, fence: &str) -> bool {
+    line.starts_with(fence)
+        && line
+            .trim_start_matches(fence.chars().next().unwrap_or('`'))
+            .is_empty()
+}
+
+/// Close the code fence, or the emphasis, code spans and table of the last block, left open at the
+/// end of the input.
+fn close_open_constructs(input: &str) -> String {
+    let mut open_fence: Option<String> = None;
+    let mut last_block_start = 0;
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let trimmed = line.trim();
+        match &open_fence {
+            Some(fence) => {
+                if closes_fence(trimmed, fence) {
+                    open_fence = None;
+                    last_block_start = offset + line.len();
+                }
+            }
+            None => {
+                if let Some(fence) = fence_opening(trimmed) {
+                    open_fence = Some(fence);
+                    last_block_start = offset;
+                } else if trimmed.is_empty() {
+                    last_block_start = offset + line.len();
+                }
+            }
+        }
+        offset += line.len();
+    }
+
+    let mut closed = input.to_string();
+    if let Some(fence) = open_fence {
+        if !closed.ends_with('\n') {
+            closed.push('\n');
+        }
+        closed.push_str(&fence);
+        return closed;
+    }
+
+    let last_block = &input[last_block_start..];
+    if let Some(delimiter_row) = missing_delimiter_row(last_block) {
+        closed.truncate(last_block_start);
+        let header = last_block.lines().next().unwrap_or_default();
+        closed.push_str(header);
+        closed.push('\n');
+        closed.push_str(&delimiter_row);
+        return closed;
+    }
+    closed.push_str(&inline_closings(last_block));
+    closed
+}
+
+/// Fence opened by a line, made of at least three backticks or tildes.
+fn fence_opening(line: &str) -> Option<String> {
+    let marker = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let fence: String = line.chars().take_while(|&c| c == marker).collect();
+    // Backtick fences cannot have backticks in their info string
+    let is_fence = fence.len() >= 3 && (marker == '~' || !line[fence.len()..].contains('`'));
+    is_fence.then_some(fence)
+}
+
+/// Delimiter row completing a table whose header row was received, but not its delimiter row.
+///
+/// Only header rows starting with a pipe are considered, so that paragraphs containing pipes are
+/// not turned into tables.
+fn missing_delimiter_row(block: &str) -> Option<String> {
+    let mut lines = block.lines();
+    let header = lines.next()?.trim();
+    if !header.starts_with('|') {
+        return None;
+    }
+    let columns = header.trim_matches('|').split('|').count();
+    match lines.next() {
+        // A partial delimiter row is replaced with a complete one
+        Some(line) if lines.next().is_none() => {
+            let line = line.trim();
+            let is_delimiter_row = line.chars().all(|c| matches!(c, '|' | '-' | ':' | ' '));
+            let cells: Vec<&str> = line.trim_matches('|').split('|').collect();
+            let is_complete = line.ends_with('|')
+                && cells.len() == columns
+                && cells.iter().all(|cell| cell.contains('-'));
+            if !is_delimiter_row || is_complete {
+                return None;
+            }
+        }
+        Some(_) => return None,
+        None => {}
+    }
+
+    Some(format!("|{}", " --- |".repeat(columns)))
+}
+
+/// Closing delimiters of the emphasis, strike-through and code spans left open in a block.
+fn inline_closings(block: &str) -> String {
+    let chars: Vec<char> = block.chars().collect();
+    let mut open: Vec<String> = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        if c == '\\' {
+            index += 2;
+            continue;
+        }
+        if !matches!(c, '*' | '_' | '~' | '`') {
+            index += 1;
+            continue;
+        }
+
+        let run_length = chars[index..]
+            .iter()
+            .take_while(|&&other| other == c)
+            .count();
+        let run: String = chars[index..index + run_length].iter().collect();
+        let before = index.checked_sub(1).map(|before| chars[before]);
+        let after = chars.get(index + run_length).copied();
+        index += run_length;
+
+        if c == '`' {
+            // The content of a code span is literal, up to a run of the same length
+            let rest: String = chars[index..].iter().collect();
+            match find_backtick_run(&rest, run_length) {
+                Some(end) => index += rest[..end].chars().count() + run_length,
+                None if after.is_some() => {
+                    return run + &open.into_iter().rev().collect::<String>();
+                }
+                None => {}
+            }
+            continue;
+        }
+
+        let can_open = after.is_some_and(|after| !after.is_whitespace())
+            && !(c == '_' && before.is_some_and(char::is_alphanumeric));
+        let can_close = before.is_some_and(|before| !before.is_whitespace());
+        if can_close && open.last() == Some(&run) {
+            open.pop();
+        } else if can_open {
+            open.push(run);
+        }
+    }
+    open.into_iter().rev().collect()
+}
+
+/// Byte offset of the first run of exactly `length` backticks.
+fn find_backtick_run(text: &str, length: usize) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find('`').map(|start| offset + start) {
+        let run_length = text[start..].chars().take_while(|&c| c == '`').count();
+        if run_length == length {
+            return Some(start);
+        }
+        offset = start + run_length;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unterminated_fence_is_closed() {
+        assert_eq!(
+            close_open_constructs("```rust\nfn main() {"),
+            "```rust\nfn main() {\n```"
+        );
+        // A shorter fence does not close a longer one
+        assert_eq!(
+            close_open_constructs("~~~~\ncode\n~~~\n"),
+            "~~~~\ncode\n~~~\n~~~~"
+        );
+        assert_eq!(
+            close_open_constructs("```\ncode\n```\n\nText"),
+            "```\ncode\n```\n\nText"
+        );
+    }
+
+    #[test]
+    fn unterminated_emphasis_is_closed() {
+        assert_eq!(close_open_constructs("Some **bold"), "Some **bold**");
+        assert_eq!(inline_closings("*nested **strong"), "***");
+        assert_eq!(inline_closings("~~gone"), "~~");
+        assert_eq!(inline_closings("**done** and _open"), "_");
+        // Intraword underscores, escapes and spaced asterisks do not open emphasis
+        assert_eq!(inline_closings("snake_case"), "");
+        assert_eq!(inline_closings("\\*literal"), "");
+        assert_eq!(inline_closings("2 * 3"), "");
+    }
+
+    #[test]
+    fn unterminated_code_span_is_closed() {
+        assert_eq!(close_open_constructs("Run `cargo"), "Run `cargo`");
+        assert_eq!(inline_closings("**bold `code"), "`**");
+        assert_eq!(inline_closings("``a`b"), "``");
+        // Delimiters inside a closed code span are literal
+        assert_eq!(inline_closings("`a*b` *c"), "*");
+    }
+
+    #[test]
+    fn half_written_table_is_completed() {
+        assert_eq!(
+            close_open_constructs("| a | b |"),
+            "| a | b |\n| --- | --- |"
+        );
+        assert_eq!(
+            close_open_constructs("Intro\n\n| a | b |\n| -"),
+            "Intro\n\n| a | b |\n| --- | --- |"
+        );
+        assert_eq!(
+            missing_delimiter_row("| a | b |\n|--"),
+            Some("| --- | --- |".to_string())
+        );
+        assert_eq!(missing_delimiter_row("| a | b |\n| --- | --- |"), None);
+        assert_eq!(
+            missing_delimiter_row("| a | b |\n| --- | --- |\n| 1 |"),
+            None
+        );
+        // Paragraphs containing pipes are not tables
+        assert_eq!(missing_delimiter_row("a | b"), None);
+    }
+
+    #[test]
+    fn fence_openings() {
+        assert_eq!(fence_opening("```rust"), Some("```".to_string()));
+        assert_eq!(fence_opening("````"), Some("````".to_string()));
+        assert_eq!(fence_opening("~~~ a`b"), Some("~~~".to_string()));
+        assert_eq!(fence_opening("``` a`b"), None);
+        assert_eq!(fence_opening("``"), None);
+        assert_eq!(fence_opening("text"), None);
+    }
+
+    #[test]
+    fn backtick_runs() {
+        assert_eq!(find_backtick_run("a ``b`` c", 2), Some(2));
+        assert_eq!(find_backtick_run("x`y", 1), Some(1));
+        assert_eq!(find_backtick_run("é`", 1), Some(2));
+        assert_eq!(find_backtick_run("a ``` b", 1), None);
+        assert_eq!(find_backtick_run("", 1), None);
+    }
+
+    #[test]
+    fn finalized_prefix_ends_before_blocks_after_blank_lines() {
+        assert_eq!(finalized_end("One\n\nTwo\n", 0, false), 5);
+        assert_eq!(finalized_end("a\n\nb\n\nc\n", 3, false), 6);
+        // The last line is not complete yet
+        assert_eq!(finalized_end("One\n\nTwo", 0, false), 0);
+        // Lists and indented content continue after blank lines
+        assert_eq!(finalized_end("- a\n\n- b\n", 0, false), 0);
+        assert_eq!(finalized_end("1. a\n\n   more\n", 0, false), 0);
+    }
+
+    #[test]
+    fn finalized_prefix_skips_fences_front_matter_and_html_blocks() {
+        assert_eq!(finalized_end("```\na\n\nb\n", 0, false), 0);
+        assert_eq!(finalized_end("```\na\n```\n\nb\n", 0, false), 11);
+
+        let front_matter = "---\ntitle: a\n\nb: c\n---\n\nText\n";
+        assert_eq!(
+            finalized_end(front_matter, 0, true),
+            front_matter.find("Text").unwrap()
+        );
+        // Without front matter, a leading `---` is a thematic break
+        assert_eq!(finalized_end("---\n\npara\n\n", 0, false), 5);
+        assert_eq!(finalized_end("---\n\npara\n\n", 0, true), 0);
+
+        let details = "<details>\n<summary>S</summary>\n\nBody\n\n</details>\n\nAfter\n";
+        assert_eq!(
+            finalized_end(details, 0, false),
+            details.find("After").unwrap()
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Resolution of relative image sources and links against the base of the document.
+
+use std::collections::HashMap;
+
+use gtk::{gio, glib, prelude::*};
+
+use crate::RenderConfig;
+
+/// Scheme of a URI, lowercased, or `None` for paths.
+///
+/// Single letter schemes are considered Windows drive letters.
+pub(crate) fn scheme(url: &str) -> Option<String> {
+    let (scheme, _) = url.split_once(':')?;
+    let is_scheme = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    is_scheme.then(|| scheme.to_ascii_lowercase())
+}
+
+/// Resolve an image source or a link against the base of the render configuration.
+///
+/// Anchors, URLs with a scheme other than `file`, and every URL when no base is set are returned
+/// unchanged. Paths are resolved into URIs, keeping their query and fragment.
+///
+/// ## Logging
+///
+/// Returns `None` with a warning log if the sandbox is enabled and the path escapes the base
+/// directory, or if the URL is a network-path reference such as `//host/path`, which has no scheme
+/// to be resolved with.
+pub(crate) fn resolve(url: &str, render_config: &RenderConfig) -> Option<String> {
+    let Some(base) = &render_config.base else {
+        return Some(url.to_string());
+    };
+    if url.is_empty() || url.starts_with('#') {
+        return Some(url.to_string());
+    }
+    if url.starts_with("//") {
+        log::warn!("refusing {url}, which has no scheme");
+        return None;
+    }
+    let (file, suffix) = match scheme(url).as_deref() {
+        Some("file") => (gio::File::for_uri(url), ""),
+        Some(_) => return Some(url.to_string()),
+        None => {
+            let end = url.find(|c| matches!(c, '?' | '#')).unwrap_or(url.len());
+            let (path, suffix) = url.split_at(end);
+            let path = glib::uri_unescape_string(path, None::<&str>)
+                .map_or_else(|| path.to_string(), |path| path.to_string());
+            (base.resolve_relative_path(path), suffix)
+        }
+    };
+    // Paths are normalized when resolved, so `..` components cannot hide an escape
+    if render_config.sandbox && !(file.equal(base) || file.has_prefix(base)) {
+        log::warn!("refusing {url}, which escapes the base directory");
+        return None;
+    }
+
+    Some(format!("{}{suffix}", file.uri()))
+}
+
+/// Image embedded for a URL, as written in the input or without its leading `./`, which is
+/// ignored in the keys as well.
+pub(crate) fn embedded_image<'a, T>(images: &'a HashMap<String, T>, url: &str) -> Option<&'a T> {
+    let relative = url.trim_start_matches("./");
+    images.get(url).or_else(|| {
+        images
+            .iter()
+            .find(|(key, _)| key.trim_start_matches("./") == relative)
+            .map(|(_, image)| image)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sandboxed(url: &str) -> Option<String> {
+        let config = RenderConfig::builder()
+            .base(&gio::File::for_path("/srv/docs"))
+            .sandbox(true)
+            .build()
+            .unwrap();
+        resolve(url, &config)
+    }
+
+    #[test]
+    fn relative_paths_are_resolved_against_the_base() {
+        assert_eq!(
+            sandboxed("img/a.png").as_deref(),
+            Some("file:///srv/docs/img/a.png")
+        );
+        assert_eq!(
+            sandboxed("./guide/../a%20b.md").as_deref(),
+            Some("file:///srv/docs/a%20b.md")
+        );
+        assert_eq!(sandboxed(".").as_deref(), Some("file:///srv/docs"));
+    }
+
+    #[test]
+    fn query_and_fragment_are_kept() {
+        assert_eq!(
+            sandboxed("a.md?v=1#usage").as_deref(),
+            Some("file:///srv/docs/a.md?v=1#usage")
+        );
+        assert_eq!(sandboxed("#usage").as_deref(), Some("#usage"));
+        assert_eq!(
+            sandboxed("https://example.com/a?b=c#d").as_deref(),
+            Some("https://example.com/a?b=c#d")
+        );
+    }
+
+    #[test]
+    fn sandbox_refuses_paths_outside_the_base() {
+        assert_eq!(sandboxed("../../etc/passwd"), None);
+        assert_eq!(sandboxed("/etc/passwd"), None);
+        assert_eq!(sandboxed("file:///etc/passwd"), None);
+        assert_eq!(sandboxed("file:///srv/docs/../secret"), None);
+        assert_eq!(sandboxed("..%2F..%2Fetc%2Fpasswd"), None);
+        // Sibling directories sharing a prefix with the base are outside of it
+        assert_eq!(sandboxed("../docs-private/a.md"), None);
+        assert_eq!(
+            sandboxed("file:///srv/docs/a.md").as_deref(),
+            Some("file:///srv/docs/a.md")
+        );
+    }
+
+    #[test]
+    fn network_path_references_are_refused() {
+        assert_eq!(sandboxed("//host/path"), None);
+    }
+
+    #[test]
+    fn paths_outside_the_base_are_resolved_without_sandbox() {
+        let config = RenderConfig::builder()
+            .base(&gio::File::for_path("/srv/docs"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            resolve("../../etc/passwd", &config).as_deref(),
+            Some("file:///etc/passwd")
+        );
+        let config = RenderConfig::default();
+        assert_eq!(resolve("../a.png", &config).as_deref(), Some("../a.png"));
+    }
+
+    #[test]
+    fn embedded_images_ignore_a_leading_dot_slash() {
+        let images = HashMap::from([
+            ("./img/a.png".to_string(), 'a'),
+            ("img/b.png".to_string(), 'b'),
+        ]);
+        assert_eq!(embedded_image(&images, "./img/a.png"), Some(&'a'));
+        assert_eq!(embedded_image(&images, "img/a.png"), Some(&'a'));
+        assert_eq!(embedded_image(&images, "./img/b.png"), Some(&'b'));
+        assert_eq!(embedded_image(&images, "img/b.png"), Some(&'b'));
+        assert_eq!(embedded_image(&images, "img/c.png"), None);
+        assert_eq!(embedded_image(&images, "/img/a.png"), None);
+    }
+}
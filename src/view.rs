@@ -0,0 +1,262 @@
+//! `MarkdownView` widget, re-rendering its markdown when its properties change.
+
+use std::{borrow::Cow, cell::Ref};
+
+use gtk::{gio, glib, prelude::*, subclass::prelude::*};
+
+use crate::{render_document, RenderConfig, RenderedDocument};
+
+mod imp {
+    use std::cell::RefCell;
+
+    use gtk::{glib, glib::once_cell::sync::Lazy, prelude::*, subclass::prelude::*};
+
+    use crate::{RenderConfig, RenderedDocument};
+
+    #[derive(Default)]
+    pub struct MarkdownView {
+        pub(super) markdown: RefCell<String>,
+        pub(super) render_config: RefCell<RenderConfig<'static>>,
+        pub(super) document: RefCell<Option<RenderedDocument>>,
+        pub(super) scrolled_window: gtk::ScrolledWindow,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for MarkdownView {
+        const NAME: &'static str = "CommonmarkMarkdownView";
+        type Type = super::MarkdownView;
+        type ParentType = gtk::Widget;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.set_layout_manager_type::<gtk::BinLayout>();
+            klass.set_css_name("markdownview");
+        }
+    }
+
+    impl ObjectImpl for MarkdownView {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+                vec![
+                    glib::ParamSpecString::builder("markdown")
+                        .default_value(Some(""))
+                        .build(),
+                    glib::ParamSpecString::builder("base-uri").build(),
+                    glib::ParamSpecString::builder("highlight-theme")
+                        .default_value(Some(RenderConfig::default().highlight_theme()))
+                        .build(),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            let value = value.get::<Option<String>>().ok().flatten();
+            match pspec.name() {
+                "markdown" => self.obj().update_markdown(value.unwrap_or_default()),
+                "base-uri" => self.obj().update_base_uri(value.as_deref()),
+                "highlight-theme" => self.obj().update_highlight_theme(value.as_deref()),
+                _ => unreachable!(),
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            let render_config = self.render_config.borrow();
+            match pspec.name() {
+                "markdown" => self.markdown.borrow().to_value(),
+                "base-uri" => render_config
+                    .base()
+                    .map(|base| base.uri().to_string())
+                    .to_value(),
+                "highlight-theme" => render_config.highlight_theme().to_value(),
+                _ => unreachable!(),
+            }
+        }
+
+        fn constructed(&self) {
+            self.parent_constructed();
+            self.scrolled_window
+                .set_hscrollbar_policy(gtk::PolicyType::Never);
+            self.scrolled_window.set_parent(&*self.obj());
+            self.obj().rerender();
+        }
+
+        fn dispose(&self) {
+            self.scrolled_window.unparent();
+        }
+    }
+
+    impl WidgetImpl for MarkdownView {}
+}
+
+glib::wrapper! {
+    /// Widget rendering markdown in a scrolled window, re-rendered when its `markdown`, `base-uri`
+    /// or `highlight-theme` property changes.
+    ///
+    /// Its type is registered by [`init`](crate::init), so that it can be used in `gtk::Builder`
+    /// `.ui` files and Blueprint as `CommonmarkMarkdownView`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let view = MarkdownView::new();
+    /// let buffer = gtk::TextBuffer::new(None);
+    /// buffer.bind_property("text", &view, "markdown").build();
+    /// ```
+    pub struct MarkdownView(ObjectSubclass<imp::MarkdownView>)
+        @extends gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl Default for MarkdownView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarkdownView {
+    /// Create an empty view, with the default render configuration.
+    pub fn new() -> Self {
+        glib::Object::builder().build()
+    }
+
+    /// Markdown rendered by the view.
+    pub fn markdown(&self) -> String {
+        self.imp().markdown.borrow().clone()
+    }
+
+    /// Set the markdown rendered by the view.
+    pub fn set_markdown(&self, markdown: &str) {
+        self.set_property("markdown", markdown);
+    }
+
+    /// URI of the directory against which relative image sources and links are resolved.
+    pub fn base_uri(&self) -> Option<String> {
+        self.property("base-uri")
+    }
+
+    /// Set the URI of the directory against which relative image sources and links are resolved.
+    pub fn set_base_uri(&self, base_uri: Option<&str>) {
+        self.set_property("base-uri", base_uri);
+    }
+
+    /// Name of the syntax highlighting theme of code blocks.
+    pub fn highlight_theme(&self) -> String {
+        self.property("highlight-theme")
+    }
+
+    /// Set the name of the syntax highlighting theme of code blocks.
+    ///
+    /// See [`available_highlight_themes`](crate::available_highlight_themes) for the accepted
+    /// names.
+    pub fn set_highlight_theme(&self, highlight_theme: &str) {
+        self.set_property("highlight-theme", highlight_theme);
+    }
+
+    /// Set the render configuration and re-render the markdown. Its base and highlight theme are
+    /// then reflected by the `base-uri` and `highlight-theme` properties.
+    pub fn set_render_config(&self, render_config: RenderConfig<'static>) {
+        self.imp().render_config.replace(render_config);
+        self.notify("base-uri");
+        self.notify("highlight-theme");
+        self.rerender();
+    }
+
+    /// Document currently rendered, to scroll to an anchor or get its outline or metadata.
+    ///
+    /// The document is borrowed from the view: the borrow must be dropped before the markdown or
+    /// the properties of the view change. There is no document while it is being updated, such as
+    /// in the handlers of its outline model.
+    pub fn document(&self) -> Option<Ref<'_, RenderedDocument>> {
+        Ref::filter_map(self.imp().document.borrow(), Option::as_ref).ok()
+    }
+
+    fn update_markdown(&self, markdown: String) {
+        if *self.imp().markdown.borrow() == markdown {
+            return;
+        }
+        self.imp().markdown.replace(markdown);
+        self.update();
+    }
+
+    fn update_base_uri(&self, base_uri: Option<&str>) {
+        let base = base_uri
+            .filter(|uri| !uri.is_empty())
+            .map(gio::File::for_uri);
+        let mut render_config = self.imp().render_config.borrow_mut();
+        let unchanged = match (&render_config.base, &base) {
+            (Some(current), Some(base)) => current.equal(base),
+            (current, base) => current.is_none() && base.is_none(),
+        };
+        if unchanged {
+            return;
+        }
+        if render_config.sandbox && base.is_none() {
+            log::warn!("the sandbox requires a base directory, keeping the current one");
+            return;
+        }
+        render_config.base = base;
+        drop(render_config);
+        self.rerender();
+    }
+
+    fn update_highlight_theme(&self, highlight_theme: Option<&str>) {
+        let default_config = RenderConfig::default();
+        let highlight_theme = highlight_theme.unwrap_or(default_config.highlight_theme());
+        if self.imp().render_config.borrow().highlight_theme == highlight_theme {
+            return;
+        }
+        let highlight_context = self.imp().render_config.borrow().highlight_context();
+        if !highlight_context
+            .theme_set()
+            .themes
+            .contains_key(highlight_theme)
+        {
+            log::warn!("unknown theme name: {highlight_theme}");
+            return;
+        }
+        self.imp().render_config.borrow_mut().highlight_theme =
+            Cow::Owned(highlight_theme.to_string());
+        self.rerender();
+    }
+
+    /// Render the markdown again in place of the current document, keeping the widgets of the
+    /// blocks that did not change.
+    ///
+    /// ## Logging
+    ///
+    /// A warning log is emitted if the markdown cannot be parsed, in which case the current
+    /// document is kept.
+    fn update(&self) {
+        let imp = self.imp();
+        // Take the document out while updating it, as the handlers of the outline model, which
+        // is updated in place, may get the document from the view
+        let Some(mut document) = imp.document.take() else {
+            return self.rerender();
+        };
+        let result = document.update(&imp.markdown.borrow(), &imp.render_config.borrow());
+        imp.document.replace(Some(document));
+        if let Err(err) = result {
+            log::warn!("unable to render markdown: {err:#}");
+        }
+    }
+
+    /// Render the markdown again, replacing the current document, after the render configuration
+    /// changed.
+    ///
+    /// ## Logging
+    ///
+    /// A warning log is emitted if the markdown cannot be parsed, in which case the current
+    /// document is kept.
+    fn rerender(&self) {
+        let imp = self.imp();
+        let document = match render_document(&imp.markdown.borrow(), &imp.render_config.borrow()) {
+            Ok(document) => document,
+            Err(err) => {
+                log::warn!("unable to render markdown: {err:#}");
+                return;
+            }
+        };
+        imp.scrolled_window.set_child(Some(document.viewport()));
+        imp.document.replace(Some(document));
+    }
+}